@@ -0,0 +1,139 @@
+//! The results of the last search, kept for vg. A result keeps the number it was listed under: result n is the n-th one.
+//!
+//! The cache is a JSON file, replaced as a whole by each search. It is written next to its final place, then renamed over
+//! it, so that readers see either the old results or the new ones, never half of them.
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use crate::matches::Match;
+
+/// Bumped whenever the file changes shape. Files of other versions are refused rather than misread.
+pub const VERSION: u32 = 1;
+
+/// A search, and what it found.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultSet {
+    pub version: u32,
+    /// Where cg ran. Relative result paths start from there.
+    pub directory: PathBuf,
+    /// The patterns searched for, first one first.
+    pub patterns: Vec<String>,
+    /// In the order they were listed, so that result n is at n - 1.
+    pub results: Vec<Match>,
+}
+
+impl ResultSet {
+    /// A result set of the current version, found from the current directory.
+    pub fn new(patterns: Vec<String>, results: Vec<Match>) -> io::Result<Self> {
+        return Ok(ResultSet { version: VERSION, directory: env::current_dir()?, patterns, results });
+    }
+    /// Result n, counting from 1.
+    pub fn get(&self, n: usize) -> Option<&Match> {
+        return n.checked_sub(1).and_then(|i| self.results.get(i));
+    }
+    /// Where the file of a result is, wherever vg runs.
+    pub fn locate(&self, m: &Match) -> PathBuf {
+        return self.directory.join(&m.path);
+    }
+}
+
+/// The cache could not be read or written.
+#[derive(Debug)]
+pub enum CacheError {
+    /// Neither XDG_CACHE_HOME nor HOME is set.
+    NoDirectory,
+    /// There is no cache yet.
+    Missing { path: PathBuf },
+    Io { path: PathBuf, error: io::Error },
+    /// The file is not a result set.
+    Invalid { path: PathBuf, message: String },
+    /// The file was written by another version of cg.
+    Version { path: PathBuf, found: u32 },
+}
+impl Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::NoDirectory => write!(f, "no cache directory, set XDG_CACHE_HOME or HOME"),
+            CacheError::Missing { path } => write!(f, "{}: no results yet, search with cg first", path.display()),
+            CacheError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            CacheError::Invalid { path, message } => write!(f, "{}: not a result set: {}", path.display(), message),
+            CacheError::Version { path, found } => write!(f, "{}: results of cache version {}, this is version {}, search again", path.display(), found, VERSION),
+        }
+    }
+}
+impl std::error::Error for CacheError {}
+
+/// Where the results of the last search are kept.
+pub fn path() -> Result<PathBuf, CacheError> {
+    let dir = env::var_os("XDG_CACHE_HOME").map(PathBuf::from).or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")));
+    return dir.map(|d| d.join("cg").join("results.json")).ok_or(CacheError::NoDirectory);
+}
+
+/// Replaces the result set at `path`, atomically.
+pub fn write(path: &Path, set: &ResultSet) -> Result<(), CacheError> {
+    let io_error = |error| CacheError::Io { path: path.to_path_buf(), error };
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(dir).map_err(io_error)?;
+    //The temporary file goes in the same directory, as renaming only is atomic within a file system.
+    let mut file = NamedTempFile::new_in(dir).map_err(io_error)?;
+    let mut out = BufWriter::new(&mut file);
+    serde_json::to_writer(&mut out, set).map_err(|e| io_error(e.into()))?;
+    out.flush().map_err(io_error)?;
+    drop(out);
+    file.as_file().sync_all().map_err(io_error)?;
+    file.persist(path).map_err(|e| io_error(e.error))?;
+    return Ok(());
+}
+
+/// Reads the result set at `path`.
+pub fn read(path: &Path) -> Result<ResultSet, CacheError> {
+    let text = match fs::read(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(CacheError::Missing { path: path.to_path_buf() }),
+        Err(error) => return Err(CacheError::Io { path: path.to_path_buf(), error }),
+    };
+    let invalid = |e: serde_json::Error| CacheError::Invalid { path: path.to_path_buf(), message: e.to_string() };
+    //The version first, as the rest may not fit.
+    #[derive(Deserialize)]
+    struct Version {
+        version: u32,
+    }
+    let found = serde_json::from_slice::<Version>(&text).map_err(invalid)?.version;
+    if found != VERSION {
+        return Err(CacheError::Version { path: path.to_path_buf(), found });
+    }
+    return serde_json::from_slice(&text).map_err(invalid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = env::temp_dir().join(format!("cg-cache-{}", std::process::id()));
+        let path = dir.join("nested").join("results.json");
+        let mut m = Match::new(PathBuf::from("src/a.rs"), 3, "let x = 1;".to_string());
+        m.column = Some(5);
+        m.spans.push(4..5);
+        let set = ResultSet { version: VERSION, directory: dir.clone(), patterns: vec!["x".to_string()], results: vec![m] };
+        write(&path, &set).unwrap();
+        write(&path, &set).unwrap();
+        let read_back = read(&path).unwrap();
+        assert_eq!(read_back, set);
+        assert_eq!(read_back.get(1).and_then(|m| m.matched()), Some("x"));
+        assert!(read_back.get(0).is_none() && read_back.get(2).is_none());
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+        fs::write(&path, r#"{"version":99,"results":"?"}"#).unwrap();
+        assert!(matches!(read(&path), Err(CacheError::Version { found: 99, .. })));
+        fs::write(&path, "{").unwrap();
+        assert!(matches!(read(&path), Err(CacheError::Invalid { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(read(&path), Err(CacheError::Missing { .. })));
+    }
+}
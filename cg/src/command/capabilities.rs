@@ -0,0 +1,277 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::io;
+use std::path::PathBuf;
+use serde::Deserialize;
+use tempfile::NamedTempFile;
+use super::files;
+use super::formatter;
+use super::tools::Grepper;
+use super::Args;
+use crate::matches::STDIN_PATH;
+
+/// Something a search may ask for, that not every backend can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// -i
+    CaseInsensitive,
+    /// Several -e patterns.
+    MultiplePatterns,
+    /// -t
+    FileTypes,
+    /// --gitignore. Backends that skip ignored files on their own have it through an entry turning that off, see `Filter::Not`.
+    Gitignore,
+    /// --column
+    Column,
+    /// -U
+    Multiline,
+    /// - as a path. Backends that have it read their own standard input, which cg hands over.
+    Stdin,
+}
+
+impl Feature {
+    /// Every feature, in declaration order.
+    pub const ALL: &'static [Feature] = &[Feature::CaseInsensitive, Feature::MultiplePatterns, Feature::FileTypes, Feature::Gitignore, Feature::Column, Feature::Multiline, Feature::Stdin];
+
+    /// The Args field that asks for the feature. None for standard input, which is a path like any other.
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            Feature::CaseInsensitive => Some("casei"),
+            Feature::MultiplePatterns => Some("patterns"),
+            Feature::FileTypes => Some("types"),
+            Feature::Gitignore => Some("gitignore"),
+            Feature::Column => Some("column"),
+            Feature::Multiline => Some("multiline"),
+            Feature::Stdin => None,
+        }
+    }
+    /// How cg makes up for a backend lacking the feature, if it can.
+    pub fn emulation(&self) -> Option<Emulation> {
+        match self {
+            Feature::CaseInsensitive | Feature::MultiplePatterns => Some(Emulation::Inline),
+            Feature::FileTypes | Feature::Gitignore => Some(Emulation::FileList),
+            Feature::Column => Some(Emulation::PostFilter),
+            Feature::Multiline => None,
+            Feature::Stdin => Some(Emulation::TempFile),
+        }
+    }
+}
+impl Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Feature::CaseInsensitive => write!(f, "case insensitive search"),
+            Feature::MultiplePatterns => write!(f, "multiple patterns"),
+            Feature::FileTypes => write!(f, "file type filters"),
+            Feature::Gitignore => write!(f, ".gitignore handling"),
+            Feature::Column => write!(f, "column numbers"),
+            Feature::Multiline => write!(f, "multiline search"),
+            Feature::Stdin => write!(f, "searching standard input"),
+        }
+    }
+}
+
+/// The ways a missing feature can be made up for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Emulation {
+    /// Rewrite the patterns, i.e. (?i) for case insensitivity, or one alternation for several patterns.
+    Inline,
+    /// Find the files to search beforehand, and hand them over as paths.
+    FileList,
+    /// Let cg work it out from the backend's output.
+    PostFilter,
+    /// Save standard input to a file, and search that instead.
+    TempFile,
+}
+
+/// A search a backend cannot do.
+#[derive(Debug)]
+pub enum CapabilityError {
+    /// The feature is missing, and cannot be emulated.
+    Unsupported { backend: String, feature: Feature },
+    /// The feature is missing, and emulating it failed.
+    Emulation { backend: String, feature: Feature, message: String },
+}
+impl Display for CapabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapabilityError::Unsupported { backend, feature } => write!(f, "backend `{}` does not support {}", backend, feature),
+            CapabilityError::Emulation { backend, feature, message } => write!(f, "backend `{}` does not support {}, and emulating it failed: {}", backend, feature, message),
+        }
+    }
+}
+impl std::error::Error for CapabilityError {}
+
+impl Args {
+    /// The features the search asks for.
+    pub fn requested(&self) -> BTreeSet<Feature> {
+        let mut r = BTreeSet::new();
+        if self.casei { r.insert(Feature::CaseInsensitive); }
+        if !self.patterns.is_empty() { r.insert(Feature::MultiplePatterns); }
+        if !self.types.is_empty() { r.insert(Feature::FileTypes); }
+        if self.gitignore { r.insert(Feature::Gitignore); }
+        if self.column { r.insert(Feature::Column); }
+        if self.multiline { r.insert(Feature::Multiline); }
+        if self.reads_stdin() { r.insert(Feature::Stdin); }
+        return r;
+    }
+}
+
+/// A search, fitted to a backend.
+#[derive(Debug)]
+pub struct Plan {
+    /// What to hand to the backend, with the emulated features already applied.
+    pub args: Args,
+    /// Where standard input was saved, for backends that cannot read it. Removed once the plan is dropped.
+    pub stdin: Option<NamedTempFile>,
+}
+
+/// How many bytes of paths a single backend command line holds at most. The file lists emulation makes up are split to
+/// fit, well under what systems take: Linux 128 KiB a word and 2 MiB in all, Windows 32767 characters in all.
+#[cfg(unix)]
+const LINE_BUDGET: usize = 128 * 1024;
+#[cfg(not(unix))]
+const LINE_BUDGET: usize = 16 * 1024;
+
+impl Plan {
+    /// The arguments of each backend run the plan takes: a single one, unless its files do not fit on one command line.
+    pub fn batches(&self) -> Vec<Args> {
+        let mut r = Vec::new();
+        let mut batch: Vec<PathBuf> = Vec::new();
+        let mut size = 0;
+        for f in &self.args.file {
+            let len = f.as_os_str().len() + 1;
+            if !batch.is_empty() && size + len > LINE_BUDGET {
+                r.push(Args { file: std::mem::take(&mut batch), ..self.args.clone() });
+                size = 0;
+            }
+            batch.push(f.clone());
+            size += len;
+        }
+        if r.is_empty() || !batch.is_empty() {
+            r.push(Args { file: batch, ..self.args.clone() });
+        }
+        return r;
+    }
+}
+
+/// Fits a search to a backend. Features the backend lacks are emulated, or refused when they cannot be.
+pub fn plan(backend: &Grepper, args: Args) -> Result<Plan, CapabilityError> {
+    return fit(backend, args, true);
+}
+
+/// The plan of a search that is only shown, not run. Standard input is left unread, and named as results from it are.
+pub fn preview(backend: &Grepper, args: Args) -> Result<Plan, CapabilityError> {
+    return fit(backend, args, false);
+}
+
+fn fit(backend: &Grepper, mut args: Args, running: bool) -> Result<Plan, CapabilityError> {
+    //Standard input has no name to filter on.
+    if args.reads_stdin() {
+        args.types.clear();
+        args.gitignore = false;
+    }
+    let missing: Vec<Feature> = args.requested().into_iter().filter(|f| !backend.capabilities.contains(f)).collect();
+    if let Some(f) = missing.iter().find(|f| f.emulation().is_none() || backend.unsupported.contains(f)) {
+        return Err(CapabilityError::Unsupported { backend: backend.name.clone(), feature: *f });
+    }
+
+    //Case first, so that the flag ends up inside each alternative.
+    if missing.contains(&Feature::CaseInsensitive) {
+        args.regex_pattern = format!("(?i){}", args.regex_pattern);
+        args.patterns = args.patterns.iter().map(|p| format!("(?i){}", p)).collect();
+        args.casei = false;
+    }
+    if missing.contains(&Feature::MultiplePatterns) {
+        let all: Vec<String> = std::iter::once(&args.regex_pattern).chain(args.patterns.iter()).map(|p| format!("(?:{})", p)).collect();
+        args.regex_pattern = all.join("|");
+        args.patterns.clear();
+    }
+    let gitignore = missing.contains(&Feature::Gitignore);
+    let types = missing.contains(&Feature::FileTypes);
+    if gitignore || types {
+        let failed = |feature: Feature, message: String| CapabilityError::Emulation { backend: backend.name.clone(), feature, message };
+        let mut globs = Vec::new();
+        if types {
+            for t in &args.types {
+                globs.extend(formatter::globs(t).map_err(|e| failed(Feature::FileTypes, e.to_string()))?);
+            }
+        }
+        let roots = if args.file.is_empty() { vec![PathBuf::from(".")] } else { args.file.clone() };
+        let list = if gitignore {
+            let mut tracked = files::tracked(&roots).map_err(|e| failed(Feature::Gitignore, e.to_string()))?;
+            tracked.retain(|p| files::matches_any(p, &globs));
+            tracked
+        } else {
+            files::walk(&roots, &globs).map_err(|e| failed(Feature::FileTypes, e.to_string()))?
+        };
+        //With no paths at all, backends would search the current directory, or stdin.
+        args.file = if list.is_empty() { vec![PathBuf::from(NULL_DEVICE)] } else { list };
+        if types { args.types.clear(); }
+        if gitignore { args.gitignore = false; }
+    }
+    //Columns are worked out again from each match as it is highlighted.
+    if missing.contains(&Feature::Column) {
+        args.column = false;
+    }
+    let mut stdin = None;
+    if missing.contains(&Feature::Stdin) {
+        //A preview leaves standard input to the search itself.
+        if running {
+            stdin = Some(save_stdin().map_err(|e| CapabilityError::Emulation { backend: backend.name.clone(), feature: Feature::Stdin, message: e.to_string() })?);
+        }
+        let saved = stdin.as_ref().map_or(PathBuf::from(STDIN_PATH), |s| s.path().to_path_buf());
+        args.file = args.file.iter().map(|f| if f.as_os_str() == Args::STDIN { saved.clone() } else { f.clone() }).collect();
+    }
+    return Ok(Plan { args, stdin });
+}
+
+/// Copies all of standard input to a temporary file.
+fn save_stdin() -> io::Result<NamedTempFile> {
+    let mut file = NamedTempFile::new()?;
+    io::copy(&mut io::stdin().lock(), &mut file)?;
+    return Ok(file);
+}
+
+#[cfg(unix)]
+const NULL_DEVICE: &str = "/dev/null";
+#[cfg(not(unix))]
+const NULL_DEVICE: &str = "NUL";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn backend(capabilities: &[Feature]) -> Grepper {
+        return Grepper { name: "x".to_string(), command: "x".to_string(), implicit: vec![], entries: vec![], capabilities: capabilities.iter().copied().collect(), unsupported: Default::default(), exit: Default::default(), environment: Default::default(), output: Default::default() };
+    }
+
+    #[test]
+    fn emulation() {
+        let args = Args::parse_from(["cg", "a", "-e", "b", "-i", "--column", "src"]);
+        let p = plan(&backend(&[]), args).unwrap();
+        assert_eq!(p.args.regex_pattern, "(?:(?i)a)|(?:(?i)b)");
+        assert!(p.args.patterns.is_empty() && !p.args.casei && !p.args.column);
+
+        let args = Args::parse_from(["cg", "a", "-e", "b", "-i"]);
+        let p = plan(&backend(&[Feature::CaseInsensitive, Feature::MultiplePatterns]), args).unwrap();
+        assert_eq!((p.args.regex_pattern.as_str(), p.args.casei), ("a", true));
+
+        let e = plan(&backend(&[]), Args::parse_from(["cg", "a", "-U"])).unwrap_err();
+        assert_eq!(e.to_string(), "backend `x` does not support multiline search");
+
+        //Filters do not apply to standard input, so nothing needs listing.
+        let p = plan(&backend(&[Feature::Stdin]), Args::parse_from(["cg", "a", "-", "-t", "rust", "--gitignore"])).unwrap();
+        assert!(p.args.requested() == [Feature::Stdin].into() && p.stdin.is_none());
+        let refusing = Grepper { unsupported: [Feature::Stdin].into(), ..backend(&[]) };
+        let e = plan(&refusing, Args::parse_from(["cg", "a", "-"])).unwrap_err();
+        assert_eq!(e.to_string(), "backend `x` does not support searching standard input");
+        let p = Plan { args: Args::parse_from(["cg", "a"]), stdin: None };
+        assert_eq!(p.batches().len(), 1);
+        let many = Plan { args: Args { file: vec![PathBuf::from("f".repeat(999)); 1000], ..p.args.clone() }, ..p };
+        assert!(many.batches().iter().all(|b| b.file.len() < 1000) && many.batches().iter().map(|b| b.file.len()).sum::<usize>() == 1000);
+        let p = preview(&backend(&[]), Args::parse_from(["cg", "a", "-"])).unwrap();
+        assert!(p.args.file == [PathBuf::from(STDIN_PATH)] && p.stdin.is_none());
+    }
+}
@@ -0,0 +1,293 @@
+//! Backend definitions, written in TOML. A definition looks like:
+//!
+//! ```toml
+//! name = "grep"            # What the backend goes by
+//! command = "grep"         # The program to run
+//! implicit = ["-r"]        # Arguments always given
+//! capabilities = ["case-insensitive", "multiple-patterns"]  # See `Feature`. The others get emulated
+//! unsupported = ["stdin"]  # Features that cannot be emulated for it either. Optional
+//!
+//! [output]                 # How to get results cg can parse, see `Output` and `Format`. Defaults to path:line:text
+//! format = "path-null"
+//! flags = ["-n", "-H", "-Z"]
+//!
+//! [exit]                   # What its exit statuses mean, see `ExitCodes`. Optional
+//! error-output = ["^ERR:"]
+//!
+//! [environment]            # How its environment is made from cg's, see `EnvironmentRules`. Optional
+//! unset = ["GREP_OPTIONS"]
+//! set = { LC_MESSAGES = "C" }
+//!
+//! [[entry]]                # One per Args field the backend understands
+//! spec = "-e!{regex_pattern}[str]"
+//! filters = ["translate:bre"]
+//! ```
+//!
+//! See `Entry::try_from` for the spec syntax, and `Filter::from_str` for the filters.
+//! The built-in definitions ship inside the binary. Definitions found in the backends folder of the system directory,
+//! then of the user directory, replace those with the same name.
+//!
+//! Next to it, config.toml holds the settings: the order in which backends are tried, and how vg opens editors.
+//! When none of the backends is installed, the native backend is used:
+//!
+//! ```toml
+//! order = ["rg", "grep"]
+//!
+//! [editors]                # By program name, the arguments that open {file} at {line} and {column}, see vg
+//! kak = ["+{line}:{column}", "{file}"]
+//! ```
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use super::capabilities::Feature;
+use super::framework::{Entry, Error, SourceFormatter};
+use super::formatter::Filter;
+use super::native;
+use super::tools::{EnvironmentRules, ExitCodes, Grepper, Output};
+use super::Args;
+use clap::Parser;
+
+/// The definitions built into cg, by file name.
+pub const BUILTIN: &[(&str, &str)] = &[
+    ("ack.toml", include_str!("../../backends/ack.toml")),
+    ("ag.toml", include_str!("../../backends/ag.toml")),
+    ("git-grep.toml", include_str!("../../backends/git-grep.toml")),
+    ("grep.toml", include_str!("../../backends/grep.toml")),
+    ("rg.toml", include_str!("../../backends/rg.toml")),
+    ("ugrep.toml", include_str!("../../backends/ugrep.toml")),
+];
+
+/// The order backends are tried in when none is asked for, most wanted first.
+pub const DEFAULT_ORDER: &[&str] = &["rg", "ugrep", "ag", "ack", "grep", "git-grep"];
+
+/// A definition that could not be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A definition file or directory could not be read.
+    Io { path: PathBuf, error: io::Error },
+    /// A definition file is not valid. The message points at the offending line.
+    Parse { path: PathBuf, message: String },
+    /// An entry cannot take the value of its source field.
+    Entry { path: PathBuf, error: Error },
+}
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ConfigError::Parse { path, message } => write!(f, "{}: {}", path.display(), message.trim_end()),
+            ConfigError::Entry { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+impl std::error::Error for ConfigError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    name: String,
+    command: String,
+    #[serde(default)]
+    implicit: Vec<String>,
+    #[serde(default)]
+    capabilities: Vec<Feature>,
+    #[serde(default)]
+    unsupported: Vec<Feature>,
+    #[serde(default)]
+    exit: ExitCodes,
+    #[serde(default)]
+    environment: EnvironmentRules,
+    #[serde(default)]
+    output: Output,
+    #[serde(default, rename = "entry")]
+    entries: Vec<EntryDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EntryDefinition {
+    spec: Spec,
+    #[serde(default)]
+    filters: Vec<FilterDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct Spec(Entry);
+impl TryFrom<String> for Spec {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let e = Entry::try_from(value.as_str()).map_err(|e| e.to_string())?;
+        match e.source_field.as_deref() {
+            Some(f) if Args::FIELDS.contains(&f) => Ok(Spec(e)),
+            f => Err(format!("unknown source field `{}`, expected one of {}", f.unwrap_or_default(), Args::FIELDS.join(", "))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct FilterDefinition(Filter);
+impl TryFrom<String> for FilterDefinition {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        return value.parse().map(FilterDefinition);
+    }
+}
+
+/// Reads a single definition. `path` is only used for error messages.
+pub fn parse(path: &Path, text: &str) -> Result<Grepper, ConfigError> {
+    let d: Definition = toml::from_str(text).map_err(|e| ConfigError::Parse { path: path.to_path_buf(), message: e.to_string() })?;
+    let mut entries = Vec::new();
+    for e in d.entries {
+        let mut entry = e.spec.0;
+        for f in e.filters {
+            entry.source = entry.source.then(f.0);
+        }
+        entries.push(entry);
+    }
+    let grepper = Grepper { name: d.name, command: d.command, implicit: d.implicit, entries, capabilities: d.capabilities.into_iter().collect(), unsupported: d.unsupported.into_iter().collect(), exit: d.exit, environment: d.environment, output: d.output };
+    if let Some(e) = grepper.exit.error_output.iter().find_map(|p| regex::Regex::new(p).err()) {
+        return Err(ConfigError::Parse { path: path.to_path_buf(), message: format!("invalid error-output regex: {}", e) });
+    }
+
+    if let Some(f) = grepper.capabilities.intersection(&grepper.unsupported).next() {
+        return Err(ConfigError::Parse { path: path.to_path_buf(), message: format!("{} is both among the capabilities and unsupported", f) });
+    }
+    //An entry for a feature the backend does not claim would be filled on top of its emulation.
+    for f in Feature::ALL.iter().filter(|f| !grepper.capabilities.contains(f)) {
+        if let Some(field) = f.field().filter(|field| grepper.entries.iter().any(|e| e.source_field.as_deref() == Some(*field))) {
+            return Err(ConfigError::Parse { path: path.to_path_buf(), message: format!("an entry reads `{}`, but {} is not among the capabilities", field, f) });
+        }
+    }
+
+    //Fill every entry once, so that type mismatches show up now rather than on some later search.
+    let probe = Args::parse_from(["cg", "cg"]);
+    for e in &grepper.entries {
+        let mut e = Entry { source: SourceFormatter::Default, ..e.clone() };
+        probe.fill(&mut e).map_err(|error| ConfigError::Entry { path: path.to_path_buf(), error })?;
+    }
+    return Ok(grepper);
+}
+
+/// The configuration directories, least important first.
+pub fn directories() -> Vec<PathBuf> {
+    let mut r = vec![PathBuf::from("/etc/cg")];
+    let user = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")));
+    if let Some(u) = user {
+        r.push(u.join("cg"));
+    }
+    return r;
+}
+
+/// Reads every .toml definition of a directory, in file name order. A missing directory holds no definitions.
+pub fn load_dir(dir: &Path) -> Result<Vec<Grepper>, ConfigError> {
+    let io_error = |error| ConfigError::Io { path: dir.to_path_buf(), error };
+    let mut paths = match fs::read_dir(dir) {
+        Ok(r) => r.map(|e| e.map(|e| e.path())).collect::<Result<Vec<_>, _>>().map_err(io_error)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error(e)),
+    };
+    paths.retain(|p| p.extension().is_some_and(|e| e == "toml"));
+    paths.sort();
+    let mut r = Vec::new();
+    for p in paths {
+        let text = fs::read_to_string(&p).map_err(|error| ConfigError::Io { path: p.clone(), error })?;
+        r.push(parse(&p, &text)?);
+    }
+    return Ok(r);
+}
+
+/// What config.toml holds.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// The backends tried when none is asked for, most wanted first. The first one installed is used.
+    #[serde(default = "default_order")]
+    pub order: Vec<String>,
+    /// Editor argument templates, by program name. They replace vg's built-in ones.
+    #[serde(default)]
+    pub editors: BTreeMap<String, Vec<String>>,
+}
+fn default_order() -> Vec<String> {
+    return DEFAULT_ORDER.iter().map(|b| b.to_string()).collect();
+}
+impl Default for Settings {
+    fn default() -> Self {
+        return Settings { order: default_order(), editors: BTreeMap::new() };
+    }
+}
+
+/// Reads config.toml. The user's file replaces the system's, and without any the defaults apply.
+pub fn settings() -> Result<Settings, ConfigError> {
+    let mut r = Settings::default();
+    for dir in directories() {
+        let path = dir.join("config.toml");
+        match fs::read_to_string(&path) {
+            Ok(text) => r = toml::from_str(&text).map_err(|e| ConfigError::Parse { path, message: e.to_string() })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => return Err(ConfigError::Io { path, error }),
+        }
+    }
+    return Ok(r);
+}
+
+/// Every known backend by name: the native one and the built-in ones, overridden by those of `directories`.
+pub fn load() -> Result<BTreeMap<String, Grepper>, ConfigError> {
+    let mut r = BTreeMap::new();
+    r.insert(native::NAME.to_string(), native::definition());
+    for (file, text) in BUILTIN {
+        let g = parse(&Path::new("<builtin>").join(file), text)?;
+        r.insert(g.name.clone(), g);
+    }
+    for dir in directories() {
+        for g in load_dir(&dir.join("backends"))? {
+            r.insert(g.name.clone(), g);
+        }
+    }
+    return Ok(r);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin() {
+        for (file, text) in BUILTIN {
+            parse(Path::new(file), text).unwrap();
+        }
+        //rg is told to search ignored files, unless --gitignore.
+        let rg = parse(Path::new("rg.toml"), BUILTIN.iter().find(|(f, _)| *f == "rg.toml").unwrap().1).unwrap();
+        assert!(rg.command_line(Args::parse_from(["cg", "a"])).unwrap().contains(&"--no-ignore".to_string()));
+        assert!(!rg.command_line(Args::parse_from(["cg", "a", "--gitignore"])).unwrap().contains(&"--no-ignore".to_string()));
+        //Patterns and paths starting with - are not options.
+        let ag = parse(Path::new("ag.toml"), BUILTIN.iter().find(|(f, _)| *f == "ag.toml").unwrap().1).unwrap();
+        let line = ag.command_line(Args::parse_from(["cg", "--", "-x", "-d"])).unwrap();
+        assert_eq!(line[line.len() - 3..], ["--", r"\-x", "-d"]);
+    }
+
+    #[test]
+    fn invalid() {
+        let e = parse(Path::new("x.toml"), "name = \"x\"\ncommand = \"x\"\n[[entry]]\nspec = \"-e!{regex_pattern}[string]\"\n").unwrap_err();
+        let message = e.to_string();
+        assert!(message.starts_with("x.toml: "));
+        assert!(message.contains("line 4"));
+        assert!(message.contains("expected a type ([str], [path], [str*], [path*], [bool] or [empty]) at position 18, found `[string`"));
+
+        let e = parse(Path::new("x.toml"), "name = \"x\"\ncommand = \"x\"\n[[entry]]\nspec = \"-e{pattern}[str]\"\n").unwrap_err();
+        assert!(e.to_string().contains("unknown source field `pattern`"));
+
+        let e = parse(Path::new("x.toml"), "name = \"x\"\ncommand = \"x\"\n[[entry]]\nspec = \"-e{file}[str]\"\n").unwrap_err();
+        assert_eq!(e.to_string(), "x.toml: entry `file`: cannot store a [path*] value in a [str] argument");
+
+        let e = parse(Path::new("x.toml"), "name = \"x\"\ncommand = \"x\"\nimplict = []\n").unwrap_err();
+        assert!(e.to_string().contains("unknown field `implict`"));
+
+        let e = parse(Path::new("x.toml"), "name = \"x\"\ncommand = \"x\"\n[[entry]]\nspec = \"-i{casei}\"\n").unwrap_err();
+        assert_eq!(e.to_string(), "x.toml: an entry reads `casei`, but case insensitive search is not among the capabilities");
+    }
+}
@@ -0,0 +1,504 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use regex_syntax::ast::parse::Parser;
+use regex_syntax::ast::{
+    Ast, AssertionKind, Class, ClassAsciiKind, ClassPerl, ClassPerlKind, ClassSet, ClassSetItem, ClassUnicode, ClassUnicodeKind,
+    Flag, Flags, FlagsItemKind, GroupKind, Literal, RepetitionKind, RepetitionRange, Span,
+};
+
+/// A regular expression flavour, as understood by a search backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    /// The regex crate's syntax, spoken by rg.
+    Rust,
+    /// Perl compatible, as in grep -P.
+    Pcre,
+    /// POSIX extended, as in grep -E.
+    Ere,
+    /// POSIX basic, plain grep.
+    Bre,
+}
+
+impl Dialect {
+    /// The characters that need a backslash to match literally.
+    fn metacharacters(&self) -> &'static str {
+        match self {
+            Dialect::Rust | Dialect::Pcre => "\\.+*?()|[]{}^$#&-~",
+            Dialect::Ere => "\\.[*+?(){|^$",
+            Dialect::Bre => "\\.[*^$",
+        }
+    }
+}
+
+/// Escapes a literal, so that it matches itself in the given dialect.
+pub fn escape(literal: &str, dialect: Dialect) -> String {
+    let meta = dialect.metacharacters();
+    let mut r = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if meta.contains(c) {
+            r.push('\\');
+        }
+        r.push(c);
+    }
+    return r;
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rust" => Ok(Dialect::Rust),
+            "pcre" => Ok(Dialect::Pcre),
+            "ere" => Ok(Dialect::Ere),
+            "bre" => Ok(Dialect::Bre),
+            _ => Err(format!("unknown dialect `{}`, expected rust, pcre, ere or bre", s)),
+        }
+    }
+}
+
+impl Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dialect::Rust => write!(f, "Rust regex"),
+            Dialect::Pcre => write!(f, "PCRE"),
+            Dialect::Ere => write!(f, "POSIX ERE"),
+            Dialect::Bre => write!(f, "POSIX BRE"),
+        }
+    }
+}
+
+/// A pattern that could not be translated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranslateError {
+    /// The pattern is not a valid Rust regex to begin with.
+    Syntax(String),
+    /// The pattern uses a construct the dialect has no way of writing.
+    Unsupported { dialect: Dialect, construct: &'static str, snippet: String, position: usize },
+}
+impl Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranslateError::Syntax(e) => write!(f, "invalid regular expression: {}", e),
+            TranslateError::Unsupported { dialect, construct, snippet, position } => write!(f, "`{}` at position {}: {} cannot be written in {}", snippet, position, construct, dialect),
+        }
+    }
+}
+impl std::error::Error for TranslateError {}
+
+/// Translates a pattern written in Rust regex syntax to the given dialect.
+///
+/// ERE and BRE are the GNU flavours: \b, \B, \| and \+ are used where POSIX has nothing.
+/// As backends search line by line, \A and \z become ^ and $ there, and case insensitive parts are spelled out as classes, i.e. (?i)ab => [aA][bB].
+pub fn translate(pattern: &str, to: Dialect) -> Result<String, TranslateError> {
+    let ast = Parser::new().parse(pattern).map_err(|e| TranslateError::Syntax(e.kind().to_string()))?;
+    if to == Dialect::Rust {
+        return Ok(pattern.to_string());
+    }
+    let mut t = Translator { pattern, to, casei: false, swap_greed: false };
+    let mut out = String::with_capacity(pattern.len());
+    t.ast(&ast, &mut out)?;
+    return Ok(out);
+}
+
+/// The flags that change how POSIX dialects are written. PCRE gets its flags as they are.
+struct Translator<'a> {
+    pattern: &'a str,
+    to: Dialect,
+    casei: bool,
+    swap_greed: bool,
+}
+
+/// A POSIX bracket expression being put together. Order matters there: ] goes first, - last, and ^ anywhere but first.
+#[derive(Default)]
+struct Bracket {
+    chars: Vec<char>,
+    ranges: Vec<(char, char)>,
+    classes: Vec<&'static str>,
+}
+
+impl Translator<'_> {
+    fn unsupported(&self, span: &Span, construct: &'static str) -> TranslateError {
+        return TranslateError::Unsupported {
+            dialect: self.to,
+            construct,
+            snippet: self.pattern[span.start.offset..span.end.offset].to_string(),
+            position: span.start.offset,
+        };
+    }
+    fn posix(&self) -> bool {
+        return self.to == Dialect::Ere || self.to == Dialect::Bre;
+    }
+    /// Writes an opening or closing group parenthesis.
+    fn paren(&self, c: char, out: &mut String) {
+        if self.to == Dialect::Bre {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    /// Applies flags to the translator, and returns them as PCRE would write them.
+    fn flags(&mut self, flags: &Flags) -> String {
+        let mut written = String::new();
+        let mut negated = false;
+        for item in &flags.items {
+            match &item.kind {
+                FlagsItemKind::Negation => {
+                    negated = true;
+                    written.push('-');
+                },
+                FlagsItemKind::Flag(flag) => match flag {
+                    Flag::CaseInsensitive => {
+                        self.casei = !negated;
+                        written.push('i');
+                    },
+                    Flag::SwapGreed => {
+                        self.swap_greed = !negated;
+                        written.push('U');
+                    },
+                    Flag::MultiLine => written.push('m'),
+                    Flag::DotMatchesNewLine => written.push('s'),
+                    //Whitespace and comments are gone from the AST, and PCRE has no Unicode toggle.
+                    Flag::IgnoreWhitespace | Flag::Unicode => (),
+                },
+            }
+        }
+        return written.trim_end_matches('-').to_string();
+    }
+
+    fn ast(&mut self, ast: &Ast, out: &mut String) -> Result<(), TranslateError> {
+        match ast {
+            Ast::Empty(_) => (),
+            Ast::Flags(set) => {
+                let written = self.flags(&set.flags);
+                if self.to == Dialect::Pcre && !written.is_empty() {
+                    out.push_str(&format!("(?{})", written));
+                }
+            },
+            Ast::Literal(l) => self.literal(l, out)?,
+            Ast::Dot(_) => out.push('.'),
+            Ast::Assertion(a) => out.push_str(match (&a.kind, self.to) {
+                (AssertionKind::StartLine, _) => "^",
+                (AssertionKind::EndLine, _) => "$",
+                (AssertionKind::StartText, Dialect::Pcre) => "\\A",
+                (AssertionKind::StartText, _) => "^",
+                (AssertionKind::EndText, Dialect::Pcre) => "\\z",
+                (AssertionKind::EndText, _) => "$",
+                (AssertionKind::WordBoundary, _) => "\\b",
+                (AssertionKind::NotWordBoundary, _) => "\\B",
+            }),
+            Ast::Class(Class::Unicode(u)) => {
+                if self.posix() {
+                    return Err(self.unsupported(&u.span, "Unicode classes"));
+                }
+                out.push_str(&self.unicode_class(u)?);
+            },
+            Ast::Class(Class::Perl(p)) => {
+                if self.posix() {
+                    out.push_str(match (&p.kind, p.negated) {
+                        (ClassPerlKind::Digit, false) => "[[:digit:]]",
+                        (ClassPerlKind::Digit, true) => "[^[:digit:]]",
+                        (ClassPerlKind::Space, false) => "[[:space:]]",
+                        (ClassPerlKind::Space, true) => "[^[:space:]]",
+                        (ClassPerlKind::Word, false) => "[[:alnum:]_]",
+                        (ClassPerlKind::Word, true) => "[^[:alnum:]_]",
+                    });
+                } else {
+                    out.push_str(perl_class(p));
+                }
+            },
+            Ast::Class(Class::Bracketed(b)) => {
+                if self.posix() {
+                    let mut bracket = Bracket::default();
+                    self.bracket_set(&b.kind, &mut bracket)?;
+                    bracket.write(b.negated, self.to, out);
+                } else {
+                    out.push('[');
+                    if b.negated {
+                        out.push('^');
+                    }
+                    self.pcre_set(&b.kind, out)?;
+                    out.push(']');
+                }
+            },
+            Ast::Repetition(r) => {
+                self.ast(&r.ast, out)?;
+                let bre = self.to == Dialect::Bre;
+                match &r.op.kind {
+                    RepetitionKind::ZeroOrOne => out.push_str(if bre { "\\?" } else { "?" }),
+                    RepetitionKind::ZeroOrMore => out.push('*'),
+                    RepetitionKind::OneOrMore => out.push_str(if bre { "\\+" } else { "+" }),
+                    RepetitionKind::Range(range) => {
+                        let range = match range {
+                            RepetitionRange::Exactly(n) => format!("{}", n),
+                            RepetitionRange::AtLeast(n) => format!("{},", n),
+                            RepetitionRange::Bounded(m, n) => format!("{},{}", m, n),
+                        };
+                        if bre {
+                            out.push_str(&format!("\\{{{}\\}}", range));
+                        } else {
+                            out.push_str(&format!("{{{}}}", range));
+                        }
+                    },
+                }
+                //Under PCRE the U flag was written out, so the repetition is written as parsed.
+                let lazy = if self.to == Dialect::Pcre { !r.greedy } else { r.greedy == self.swap_greed };
+                if lazy {
+                    if self.posix() {
+                        return Err(self.unsupported(&r.span, "lazy repetitions"));
+                    }
+                    out.push('?');
+                }
+            },
+            Ast::Group(g) => {
+                let (casei, swap_greed) = (self.casei, self.swap_greed);
+                match &g.kind {
+                    GroupKind::CaptureIndex(_) => self.paren('(', out),
+                    GroupKind::CaptureName(name) => {
+                        self.paren('(', out);
+                        if self.to == Dialect::Pcre {
+                            out.push_str(&format!("?P<{}>", name.name));
+                        }
+                    },
+                    GroupKind::NonCapturing(flags) => {
+                        let written = self.flags(flags);
+                        self.paren('(', out);
+                        if self.to == Dialect::Pcre {
+                            out.push_str(&format!("?{}:", written));
+                        }
+                    },
+                }
+                self.ast(&g.ast, out)?;
+                self.paren(')', out);
+                self.casei = casei;
+                self.swap_greed = swap_greed;
+            },
+            Ast::Alternation(a) => {
+                for (i, branch) in a.asts.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(if self.to == Dialect::Bre { "\\|" } else { "|" });
+                    }
+                    self.ast(branch, out)?;
+                }
+            },
+            Ast::Concat(c) => {
+                for item in &c.asts {
+                    self.ast(item, out)?;
+                }
+            },
+        }
+        return Ok(());
+    }
+
+    fn literal(&self, l: &Literal, out: &mut String) -> Result<(), TranslateError> {
+        if self.posix() {
+            if l.c == '\n' {
+                return Err(self.unsupported(&l.span, "newlines"));
+            }
+            let folded = case_variants(l.c);
+            if self.casei && folded.len() > 1 {
+                Bracket { chars: folded, ..Bracket::default() }.write(false, self.to, out);
+            } else {
+                out.push_str(&escape(&l.c.to_string(), self.to));
+            }
+        } else if l.c.is_control() {
+            out.push_str(&format!("\\x{{{:x}}}", l.c as u32));
+        } else {
+            out.push_str(&escape(&l.c.to_string(), self.to));
+        }
+        return Ok(());
+    }
+
+    fn unicode_class(&self, u: &ClassUnicode) -> Result<String, TranslateError> {
+        let p = if u.negated { 'P' } else { 'p' };
+        match &u.kind {
+            ClassUnicodeKind::OneLetter(c) => Ok(format!("\\{}{}", p, c)),
+            ClassUnicodeKind::Named(name) => Ok(format!("\\{}{{{}}}", p, name)),
+            ClassUnicodeKind::NamedValue { .. } => Err(self.unsupported(&u.span, "Unicode property values")),
+        }
+    }
+
+    /// Writes the inside of a PCRE class. PCRE has no nested classes, so non negated ones are merged in.
+    fn pcre_set(&self, set: &ClassSet, out: &mut String) -> Result<(), TranslateError> {
+        let item = match set {
+            ClassSet::Item(item) => item,
+            ClassSet::BinaryOp(op) => return Err(self.unsupported(&op.span, "class set operations")),
+        };
+        match item {
+            ClassSetItem::Empty(_) => (),
+            ClassSetItem::Literal(l) => out.push_str(&pcre_class_literal(l.c)),
+            ClassSetItem::Range(r) => out.push_str(&format!("{}-{}", pcre_class_literal(r.start.c), pcre_class_literal(r.end.c))),
+            ClassSetItem::Ascii(a) => out.push_str(&format!("[:{}{}:]", if a.negated { "^" } else { "" }, ascii_class(&a.kind))),
+            ClassSetItem::Unicode(u) => out.push_str(&self.unicode_class(u)?),
+            ClassSetItem::Perl(p) => out.push_str(perl_class(p)),
+            ClassSetItem::Bracketed(b) if b.negated => return Err(self.unsupported(&b.span, "nested negated classes")),
+            ClassSetItem::Bracketed(b) => self.pcre_set(&b.kind, out)?,
+            ClassSetItem::Union(u) => {
+                for item in &u.items {
+                    self.pcre_set(&ClassSet::Item(item.clone()), out)?;
+                }
+            },
+        }
+        return Ok(());
+    }
+
+    /// Gathers the inside of a POSIX bracket expression.
+    fn bracket_set(&self, set: &ClassSet, bracket: &mut Bracket) -> Result<(), TranslateError> {
+        let item = match set {
+            ClassSet::Item(item) => item,
+            ClassSet::BinaryOp(op) => return Err(self.unsupported(&op.span, "class set operations")),
+        };
+        match item {
+            ClassSetItem::Empty(_) => (),
+            ClassSetItem::Literal(l) if l.c == '\n' => return Err(self.unsupported(&l.span, "newlines")),
+            ClassSetItem::Literal(l) if self.casei => bracket.chars.extend(case_variants(l.c)),
+            ClassSetItem::Literal(l) => bracket.chars.push(l.c),
+            ClassSetItem::Range(r) => {
+                bracket.ranges.push((r.start.c, r.end.c));
+                let letters = r.start.c.is_ascii_alphabetic() && r.end.c.is_ascii_alphabetic();
+                if self.casei && letters && r.start.c.is_ascii_lowercase() == r.end.c.is_ascii_lowercase() {
+                    let flip = |c: char| if c.is_ascii_lowercase() { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() };
+                    bracket.ranges.push((flip(r.start.c), flip(r.end.c)));
+                }
+            },
+            ClassSetItem::Ascii(a) if a.negated => return Err(self.unsupported(&a.span, "negated classes inside brackets")),
+            ClassSetItem::Ascii(a) if a.kind == ClassAsciiKind::Word => {
+                bracket.classes.push("alnum");
+                bracket.chars.push('_');
+            },
+            ClassSetItem::Ascii(a) => bracket.classes.push(ascii_class(&a.kind)),
+            ClassSetItem::Unicode(u) => return Err(self.unsupported(&u.span, "Unicode classes")),
+            ClassSetItem::Perl(p) if p.negated => return Err(self.unsupported(&p.span, "negated classes inside brackets")),
+            ClassSetItem::Perl(p) => match p.kind {
+                ClassPerlKind::Digit => bracket.classes.push("digit"),
+                ClassPerlKind::Space => bracket.classes.push("space"),
+                ClassPerlKind::Word => {
+                    bracket.classes.push("alnum");
+                    bracket.chars.push('_');
+                },
+            },
+            ClassSetItem::Bracketed(b) if b.negated => return Err(self.unsupported(&b.span, "nested negated classes")),
+            ClassSetItem::Bracketed(b) => self.bracket_set(&b.kind, bracket)?,
+            ClassSetItem::Union(u) => {
+                for item in &u.items {
+                    self.bracket_set(&ClassSet::Item(item.clone()), bracket)?;
+                }
+            },
+        }
+        return Ok(());
+    }
+}
+
+impl Bracket {
+    fn write(mut self, negated: bool, dialect: Dialect, out: &mut String) {
+        self.chars.dedup();
+        let caret = self.chars.contains(&'^');
+        let dash = self.chars.contains(&'-');
+        let close = self.chars.contains(&']');
+        self.chars.retain(|c| !"^-]".contains(*c));
+        let others = !self.chars.is_empty() || !self.ranges.is_empty() || !self.classes.is_empty() || close || dash;
+        if caret && !others && !negated {
+            out.push_str(&escape("^", dialect));
+            return;
+        }
+        out.push('[');
+        if negated {
+            out.push('^');
+        }
+        if close {
+            out.push(']');
+        }
+        out.extend(self.chars.iter());
+        for (start, end) in &self.ranges {
+            out.push_str(&format!("{}-{}", start, end));
+        }
+        for class in &self.classes {
+            out.push_str(&format!("[:{}:]", class));
+        }
+        if caret {
+            out.push('^');
+        }
+        if dash {
+            out.push('-');
+        }
+        out.push(']');
+    }
+}
+
+/// The character and its other cases, if any.
+fn case_variants(c: char) -> Vec<char> {
+    let mut r = vec![c];
+    for v in c.to_lowercase().chain(c.to_uppercase()) {
+        if !r.contains(&v) && v.to_lowercase().count() == 1 {
+            r.push(v);
+        }
+    }
+    return r;
+}
+
+fn pcre_class_literal(c: char) -> String {
+    if c.is_control() {
+        return format!("\\x{{{:x}}}", c as u32);
+    }
+    if "\\]^-[".contains(c) {
+        return format!("\\{}", c);
+    }
+    return c.to_string();
+}
+
+fn perl_class(p: &ClassPerl) -> &'static str {
+    match (&p.kind, p.negated) {
+        (ClassPerlKind::Digit, false) => "\\d",
+        (ClassPerlKind::Digit, true) => "\\D",
+        (ClassPerlKind::Space, false) => "\\s",
+        (ClassPerlKind::Space, true) => "\\S",
+        (ClassPerlKind::Word, false) => "\\w",
+        (ClassPerlKind::Word, true) => "\\W",
+    }
+}
+
+fn ascii_class(kind: &ClassAsciiKind) -> &'static str {
+    match kind {
+        ClassAsciiKind::Alnum => "alnum",
+        ClassAsciiKind::Alpha => "alpha",
+        ClassAsciiKind::Ascii => "ascii",
+        ClassAsciiKind::Blank => "blank",
+        ClassAsciiKind::Cntrl => "cntrl",
+        ClassAsciiKind::Digit => "digit",
+        ClassAsciiKind::Graph => "graph",
+        ClassAsciiKind::Lower => "lower",
+        ClassAsciiKind::Print => "print",
+        ClassAsciiKind::Punct => "punct",
+        ClassAsciiKind::Space => "space",
+        ClassAsciiKind::Upper => "upper",
+        ClassAsciiKind::Word => "word",
+        ClassAsciiKind::Xdigit => "xdigit",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translations() {
+        let t = |p: &str, d: Dialect| translate(p, d).unwrap();
+        assert_eq!(t(r"\d+\.rs$", Dialect::Bre), r"[[:digit:]]\+\.rs$");
+        assert_eq!(t(r"\d+\.rs$", Dialect::Ere), r"[[:digit:]]+\.rs$");
+        assert_eq!(t(r"\d+\.rs$", Dialect::Pcre), r"\d+\.rs$");
+        assert_eq!(t(r"(?i)ab|c{2,3}", Dialect::Bre), r"[aA][bB]\|[cC]\{2,3\}");
+        assert_eq!(t(r"(?i)ab|c{2,3}", Dialect::Pcre), r"(?i)ab|c{2,3}");
+        assert_eq!(t(r"\bfn (?P<name>\w+)\(", Dialect::Ere), r"\bfn ([[:alnum:]_]+)\(");
+        assert_eq!(t(r"[]a^-]", Dialect::Ere), r"[]a^-]");
+        assert_eq!(t(r"[\d_.\[]", Dialect::Pcre), r"[\d_.\[]");
+        assert_eq!(t(r"x(?i:[a-c])y", Dialect::Ere), r"x([a-cA-C])y");
+    }
+
+    #[test]
+    fn untranslatable() {
+        let e = translate(r"a+?b", Dialect::Ere).unwrap_err();
+        assert_eq!(e.to_string(), "`a+?` at position 0: lazy repetitions cannot be written in POSIX ERE");
+        let e = translate(r"x\p{Greek}", Dialect::Bre).unwrap_err();
+        assert!(matches!(e, TranslateError::Unsupported { position: 1, construct: "Unicode classes", .. }));
+        assert!(matches!(translate(r"[a-z&&[^x]]", Dialect::Pcre), Err(TranslateError::Unsupported { construct: "class set operations", .. })));
+        assert!(matches!(translate(r"(unclosed", Dialect::Pcre), Err(TranslateError::Syntax(_))));
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use crate::pipeline::{Pipeline, Redirect, Stage};
+
+/// Matches a file name against a glob made of literal characters, * (any run of characters) and ? (any one character).
+pub fn glob_match(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    //Classic backtracking on the last star.
+    let (mut g, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if g < glob.len() && (glob[g] == '?' || glob[g] == name[n]) {
+            g += 1;
+            n += 1;
+        } else if g < glob.len() && glob[g] == '*' {
+            star = Some((g, n));
+            g += 1;
+        } else if let Some((sg, sn)) = star {
+            g = sg + 1;
+            n = sn + 1;
+            star = Some((sg, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    return glob[g..].iter().all(|c| *c == '*');
+}
+
+/// Whether the file name of a path matches any of the globs. No globs at all match everything.
+pub fn matches_any(path: &Path, globs: &[String]) -> bool {
+    if globs.is_empty() {
+        return true;
+    }
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    return globs.iter().any(|g| glob_match(g, &name));
+}
+
+/// Every file below the roots whose name matches the globs, sorted. Roots that are files are kept as they are.
+/// As with grep -r, symbolic links are only followed when they are roots, and .git directories are not entered.
+/// Entries that cannot be read are reported on stderr and skipped.
+pub fn walk(roots: &[PathBuf], globs: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut r = Vec::new();
+    let mut pending: Vec<PathBuf> = Vec::new();
+    for root in roots {
+        let meta = fs::metadata(root).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", root.display(), e)))?;
+        if meta.is_dir() {
+            pending.push(root.clone());
+        } else {
+            r.push(root.clone());
+        }
+    }
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(e) => {
+                eprintln!("cg: {}: {}", dir.display(), e);
+                continue;
+            },
+        };
+        for e in entries {
+            let (path, kind) = match e.and_then(|e| Ok((e.path(), e.file_type()?))) {
+                Ok(e) => e,
+                Err(e) => {
+                    eprintln!("cg: {}: {}", dir.display(), e);
+                    continue;
+                },
+            };
+            if kind.is_dir() {
+                if path.file_name() != Some(".git".as_ref()) {
+                    pending.push(path);
+                }
+            } else if kind.is_file() && matches_any(&path, globs) {
+                r.push(path);
+            }
+        }
+    }
+    r.sort();
+    return Ok(r);
+}
+
+/// The files below the roots that git does not ignore, tracked or not, sorted.
+pub fn tracked(roots: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let roots: Vec<String> = roots.iter().map(|r| r.to_string_lossy().into_owned()).collect();
+    let git = Stage::new("git").args(&["ls-files", "-z", "--cached", "--others", "--exclude-standard", "--"]).args(&roots).stdout(Redirect::Pipe).stderr(Redirect::Pipe);
+    let mut running = Pipeline::new().stage(git).spawn().map_err(io::Error::other)?;
+    let mut out = Vec::new();
+    if let Some(mut stdout) = running.stdout.take() {
+        stdout.read_to_end(&mut out)?;
+    }
+    let report = running.wait().map_err(io::Error::other)?;
+    if let Some(f) = report.failure() {
+        return Err(io::Error::other(f.to_string()));
+    }
+    let mut r: Vec<PathBuf> = out.split(|b| *b == 0)
+        .filter(|p| !p.is_empty())
+        .map(|p| PathBuf::from(String::from_utf8_lossy(p).into_owned()))
+        .collect();
+    r.sort();
+    return Ok(r);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "main.rs.orig"));
+        assert!(glob_match("*.rs*", "main.rs.orig"));
+        assert!(glob_match("?a*b", "xaab"));
+        assert!(!glob_match("?a*b", "ab"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links() {
+        let dir = std::env::temp_dir().join(format!("cg-walk-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("a.rs"), "").unwrap();
+        std::os::unix::fs::symlink("missing", dir.join("dangling.rs")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("sub").join("loop")).unwrap();
+        let found = walk(std::slice::from_ref(&dir), &["*.rs".to_string()]);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found.unwrap(), [dir.join("sub").join("a.rs")]);
+    }
+}
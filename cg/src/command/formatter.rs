@@ -0,0 +1,181 @@
+use std::env;
+use std::str::FromStr;
+use std::path::{Path, PathBuf};
+use super::dialect::{self, Dialect};
+use super::framework::{Argument, ErrorKind, SourceFormatter};
+
+/// Globs for each file type name, as used by `Filter::FileType`.
+pub const FILE_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx", "*.h"]),
+    ("css", &["*.css", "*.scss"]),
+    ("go", &["*.go"]),
+    ("html", &["*.html", "*.htm"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// A single step of a `SourceFormatter::Filter` chain.
+/// Filters only ever see filled values: missing values and `Argument::Empty` go through untouched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Escapes regex metacharacters, so that text matches literally in the given dialect.
+    Escape(Dialect),
+    /// Translates a pattern from Rust regex syntax to the given dialect.
+    Translate(Dialect),
+    /// Replaces file type names by their globs, i.e. rust => *.rs
+    FileType,
+    /// Makes paths relative to the root of the enclosing git repository. Paths outside of it are left alone.
+    RepoRelative,
+    /// Replaces a value by another, i.e. true => insensitive. Booleans are compared as true or false, and become text when replaced.
+    Map(String, String),
+    /// Turns a boolean flag around, for flags given when an option is off, i.e. --no-ignore.
+    Not,
+}
+
+impl Filter {
+    /// The name of the filter, for error messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Escape(_) => "escape",
+            Filter::Translate(_) => "translate",
+            Filter::FileType => "file-type",
+            Filter::RepoRelative => "repo-relative",
+            Filter::Map(_, _) => "map",
+            Filter::Not => "not",
+        }
+    }
+    fn apply(&self, value: Argument) -> Result<Argument, ErrorKind> {
+        match (self, value) {
+            (_, v @ Argument::Empty(_)) => Ok(v),
+            (Filter::Escape(d), Argument::Text(x)) => Ok(Argument::Text(x.map(|t| dialect::escape(&t, *d)))),
+            (Filter::Escape(d), Argument::CollectionText(x)) => Ok(Argument::CollectionText(x.map(|v| v.iter().map(|t| dialect::escape(t, *d)).collect()))),
+            (Filter::Translate(d), Argument::Text(x)) => Ok(Argument::Text(x.map(|t| translate(&t, *d)).transpose()?)),
+            (Filter::Translate(d), Argument::CollectionText(x)) => Ok(Argument::CollectionText(x.map(|v| {
+                v.iter().map(|t| translate(t, *d)).collect::<Result<Vec<_>, _>>()
+            }).transpose()?)),
+            (Filter::FileType, Argument::Text(x)) => Ok(Argument::CollectionText(x.map(|t| globs(&t)).transpose()?)),
+            (Filter::FileType, Argument::CollectionText(x)) => Ok(Argument::CollectionText(x.map(|v| {
+                v.iter().map(|t| globs(t)).collect::<Result<Vec<_>, _>>().map(|g| g.concat())
+            }).transpose()?)),
+            (Filter::RepoRelative, Argument::PathPattern(x)) => Ok(Argument::PathPattern(x.map(|p| repo_relative(&p)))),
+            (Filter::RepoRelative, Argument::CollectionPathPattern(x)) => Ok(Argument::CollectionPathPattern(x.map(|v| v.iter().map(|p| repo_relative(p)).collect()))),
+            (Filter::Map(from, to), Argument::Text(x)) => Ok(Argument::Text(x.map(|t| if &t == from { to.clone() } else { t }))),
+            (Filter::Map(from, to), Argument::CollectionText(x)) => Ok(Argument::CollectionText(x.map(|v| v.into_iter().map(|t| if &t == from { to.clone() } else { t }).collect()))),
+            (Filter::Map(from, to), Argument::BooleanFlag(Some(b))) if &b.to_string() == from => Ok(Argument::Text(Some(to.clone()))),
+            (Filter::Map(_, _), v @ Argument::BooleanFlag(_)) => Ok(v),
+            (Filter::Not, Argument::BooleanFlag(x)) => Ok(Argument::BooleanFlag(x.map(|b| !b))),
+            (_, v) => Err(ErrorKind::UnsupportedFilter { filter: self.name(), kind: v.kind() }),
+        }
+    }
+}
+
+fn translate(pattern: &str, to: Dialect) -> Result<String, ErrorKind> {
+    return dialect::translate(pattern, to).map_err(ErrorKind::Translation);
+}
+
+/// Filters as written in backend definitions: escape:<dialect>, translate:<dialect>, file-type, repo-relative, map:<from>=<to> and not.
+impl FromStr for Filter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameter) = match s.split_once(':') {
+            Some((n, p)) => (n, Some(p)),
+            None => (s, None),
+        };
+        match (name, parameter) {
+            ("escape", Some(d)) => Ok(Filter::Escape(d.parse()?)),
+            ("translate", Some(d)) => Ok(Filter::Translate(d.parse()?)),
+            ("file-type", None) => Ok(Filter::FileType),
+            ("repo-relative", None) => Ok(Filter::RepoRelative),
+            ("not", None) => Ok(Filter::Not),
+            ("map", Some(m)) => match m.split_once('=') {
+                Some((from, to)) => Ok(Filter::Map(from.to_string(), to.to_string())),
+                None => Err(format!("invalid filter `{}`, expected map:<from>=<to>", s)),
+            },
+            ("escape" | "translate", None) => Err(format!("invalid filter `{}`, expected {}:<dialect>", s, name)),
+            ("file-type" | "repo-relative" | "not", Some(_)) => Err(format!("invalid filter `{}`, {} takes no parameter", s, name)),
+            _ => Err(format!("unknown filter `{}`, expected escape, translate, file-type, repo-relative, map or not", name)),
+        }
+    }
+}
+
+/// The globs of a file type.
+pub fn globs(file_type: &str) -> Result<Vec<String>, ErrorKind> {
+    return FILE_TYPES.iter()
+        .find(|(name, _)| *name == file_type)
+        .map(|(_, globs)| globs.iter().map(|g| g.to_string()).collect())
+        .ok_or_else(|| ErrorKind::UnknownFileType(file_type.to_string()));
+}
+
+/// The closest directory above the current one holding a .git entry.
+pub fn repo_root() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    return cwd.ancestors().find(|d| d.join(".git").exists()).map(Path::to_path_buf);
+}
+
+fn repo_relative(path: &Path) -> PathBuf {
+    let root = match repo_root().and_then(|r| r.canonicalize().ok()) {
+        Some(r) => r,
+        None => return path.to_path_buf(),
+    };
+    let absolute = match path.canonicalize() {
+        Ok(p) => p,
+        Err(_) => return path.to_path_buf(),
+    };
+    match absolute.strip_prefix(&root) {
+        Ok(p) if p.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(p) => p.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+impl SourceFormatter {
+    /// Appends a filter to the chain.
+    pub fn then(self, filter: Filter) -> Self {
+        match self {
+            SourceFormatter::Default => SourceFormatter::Filter(vec![filter]),
+            SourceFormatter::Filter(mut chain) => {
+                chain.push(filter);
+                return SourceFormatter::Filter(chain);
+            },
+        }
+    }
+    /// Runs a freshly transformed value through the chain.
+    pub fn format(&self, value: Argument) -> Result<Argument, ErrorKind> {
+        match self {
+            SourceFormatter::Default => Ok(value),
+            SourceFormatter::Filter(chain) => chain.iter().try_fold(value, |v, f| f.apply(v)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain() {
+        let f = SourceFormatter::Default.then(Filter::Map("cpp".to_string(), "c".to_string())).then(Filter::FileType);
+        let r = f.format(Argument::CollectionText(Some(vec!["cpp".to_string(), "rust".to_string()]))).unwrap();
+        assert!(matches!(r, Argument::CollectionText(Some(v)) if v == ["*.c", "*.h", "*.rs"]));
+
+        let f = SourceFormatter::Default.then(Filter::Escape(Dialect::Bre));
+        let r = f.format(Argument::Text(Some("a.b+c*".to_string()))).unwrap();
+        assert!(matches!(r, Argument::Text(Some(t)) if t == r"a\.b+c\*"));
+
+        let f = SourceFormatter::Default.then(Filter::Map("true".to_string(), "smart".to_string()));
+        assert!(matches!(f.format(Argument::BooleanFlag(Some(true))).unwrap(), Argument::Text(Some(t)) if t == "smart"));
+        assert!(matches!(f.format(Argument::BooleanFlag(Some(false))).unwrap(), Argument::BooleanFlag(Some(false))));
+
+        let f = SourceFormatter::Default.then(Filter::FileType);
+        assert_eq!(f.format(Argument::BooleanFlag(Some(true))).unwrap_err(), ErrorKind::UnsupportedFilter { filter: "file-type", kind: "bool" });
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::{BTreeSet};
 use std::path::PathBuf;
 use std::fmt::{self, Display};
 use regex::Regex;
+use super::formatter::Filter;
+use super::dialect::TranslateError;
 
 type Index = u8;
 
@@ -30,23 +32,10 @@ impl Display for Name {
             Name::Short(c) => write!(f, "-{}", c),
             Name::Long(s) => write!(f, "--{}", s),
             Name::Blank(_) => fmt::Result::Ok(()), //Nothing displayed!
-            _ => panic!("Unsupported name type!"),
+            Name::Undefined => fmt::Result::Ok(()), //Skipped, so nothing displayed either.
         }
     }
 }
-impl TryFrom<(regex::Match<'_>, &str)> for Name {
-    type Error = Error;
-    fn try_from(value: (regex::Match, &str)) -> Result<Self, Self::Error> {
-        let s = value.0.as_str();
-        match value.1 {
-            "d1" => Ok(Name::Blank(s[1..].parse().unwrap())),
-            "d2" => Ok(Name::Short(s.chars().nth(1).unwrap())),
-            "d3" => Ok(Name::Long(s[2..].to_string())),
-            _ => Err(Error {  })
-        } 
-    }
-}
-
 #[derive(Debug, Clone)]
 pub enum Argument {
     /// A collection type. Hopefully.
@@ -62,12 +51,25 @@ pub enum Argument {
     /// Nothing
     Empty(Option<()>),
 }
+impl Argument {
+    /// The kind of argument, as written between brackets in an entry spec.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Argument::CollectionText(_) => "str*",
+            Argument::PathPattern(_) => "path",
+            Argument::CollectionPathPattern(_) => "path*",
+            Argument::Text(_) => "str",
+            Argument::BooleanFlag(_) => "bool",
+            Argument::Empty(_) => "empty",
+        }
+    }
+}
 trait Transform<T> {
-    fn transform(&mut self, value: &T);
+    fn transform(&mut self, value: &T) -> Result<(), ErrorKind>;
 }
 pub trait Generate {
     /// Vec and not option, because of collection arguments.
-    fn generate(self) -> Vec<String>;
+    fn generate(self) -> Result<Vec<String>, Error>;
 }
 
 /// All the From<T> for argument help implement default value conversion. they are, in no way, needed.
@@ -110,38 +112,44 @@ where Argument: Transform<T> {
 /// To implement Transform<T> for a new T, you need to implement the transform function. 
 ///   That is, provide a match against all valid transformation fields, to cast into self.
 ///   You should always provide a case to cast into empty. Thankfully, that cast is always the same.
+///   Anything else is an `ErrorKind::UnsupportedTransformation`.
 impl Transform<String> for Argument {
-    fn transform(&mut self, value: &String) {
+    fn transform(&mut self, value: &String) -> Result<(), ErrorKind> {
         match self {
             Argument::Text(x) => {*x = Some(value.to_string())},
+            Argument::CollectionText(x) => {*x = Some(vec![value.to_string()])},
             Argument::Empty(x) => {*x = Some(())}
-            _ => panic!("Unspported transformation!")
+            _ => return Err(ErrorKind::UnsupportedTransformation { from: "str", to: self.kind() }),
         }
+        return Ok(());
     }
 }
 impl Transform<PathBuf> for Argument {
-    fn transform(&mut self, value: &PathBuf) {
+    fn transform(&mut self, value: &PathBuf) -> Result<(), ErrorKind> {
         match self {
             Argument::PathPattern(x) => {*x = Some(value.to_path_buf())},
+            Argument::CollectionPathPattern(x) => {*x = Some(vec![value.to_path_buf()])},
             Argument::Text(x) => {*x = Some(value.display().to_string())},
             Argument::Empty(x) => {*x = Some(())}
-            _ => panic!("Unspported transformation!")
+            _ => return Err(ErrorKind::UnsupportedTransformation { from: "path", to: self.kind() }),
         }
+        return Ok(());
     }
 }
 impl Transform<bool> for Argument {
-    fn transform(&mut self, value: &bool) {
+    fn transform(&mut self, value: &bool) -> Result<(), ErrorKind> {
         match self {
             Argument::BooleanFlag(x) => {*x = Some(*value)},
             Argument::Text(x) => {*x = Some(value.to_string())},
             Argument::Empty(x) => {*x = Some(())}
-            _ => panic!("Unspported transformation!")
+            _ => return Err(ErrorKind::UnsupportedTransformation { from: "bool", to: self.kind() }),
         }
+        return Ok(());
     }
 }
 
 impl Transform<Option<PathBuf>> for Argument {
-    fn transform(&mut self, value: &Option<PathBuf>) {
+    fn transform(&mut self, value: &Option<PathBuf>) -> Result<(), ErrorKind> {
         match self {
             Argument::PathPattern(x) => match value {
                 Some(p) => {*x = Some(p.to_path_buf())},
@@ -152,88 +160,138 @@ impl Transform<Option<PathBuf>> for Argument {
                 None => {*x = None},
             },
             Argument::Empty(x) => {*x = Some(())}
-            _ => panic!("Unspported transformation!")
+            _ => return Err(ErrorKind::UnsupportedTransformation { from: "path", to: self.kind() }),
         }
+        return Ok(());
     }
 }
 
 
-impl Generate for Entry {
-    fn generate(self) -> Vec<String> {
-        match self.target_type {
-            Argument::BooleanFlag(x) => optional_vectorization(x, &self.target_name, &self.defaults_to.into()),
-            Argument::Text(x) => optional_vectorization(x, &self.target_name, &self.defaults_to.into()),
-            Argument::PathPattern(x) => optional_vectorization(x, &self.target_name, &self.defaults_to.into()),
-            Argument::Empty(x) => optional_vectorization(x, &self.target_name, &self.defaults_to.into()),
-            _ => panic!("Unsupported type: {:?}", self),
+/// Collections are left empty when clap gives no element, so that the entry's default applies.
+impl Transform<Vec<String>> for Argument {
+    fn transform(&mut self, value: &Vec<String>) -> Result<(), ErrorKind> {
+        let v = if value.is_empty() { None } else { Some(value.clone()) };
+        match self {
+            Argument::CollectionText(x) => {*x = v},
+            Argument::Empty(x) => {*x = Some(())}
+            _ => return Err(ErrorKind::UnsupportedTransformation { from: "str*", to: self.kind() }),
+        }
+        return Ok(());
+    }
+}
+impl Transform<Vec<PathBuf>> for Argument {
+    fn transform(&mut self, value: &Vec<PathBuf>) -> Result<(), ErrorKind> {
+        let v = if value.is_empty() { None } else { Some(value.clone()) };
+        match self {
+            Argument::CollectionPathPattern(x) => {*x = v},
+            Argument::CollectionText(x) => {*x = v.map(|v| v.iter().map(|p| p.display().to_string()).collect())},
+            Argument::Empty(x) => {*x = Some(())}
+            _ => return Err(ErrorKind::UnsupportedTransformation { from: "path*", to: self.kind() }),
         }
+        return Ok(());
+    }
+}
+
+impl Generate for Entry {
+    fn generate(self) -> Result<Vec<String>, Error> {
+        let entry = self.label();
+        return argument_vectorization(self.target_type, &self.target_name, self.value_style, &self.defaults_to.into()).map_err(|kind| Error { entry, kind });
     }
 }
 
 trait Vectorize
     where Self: Sized {
-    fn vec(self, name: &Name) -> Vec<String>; //
+    fn vec(self, name: &Name, style: ValueStyle) -> Result<Vec<String>, ErrorKind>; //
+}
+fn argument_vectorization(v: Argument, name: &Name, style: ValueStyle, defaults: &Argument) -> Result<Vec<String>, ErrorKind> {
+    match v {
+        Argument::BooleanFlag(x) => optional_vectorization(x, name, style, defaults),
+        Argument::Text(x) => optional_vectorization(x, name, style, defaults),
+        Argument::PathPattern(x) => optional_vectorization(x, name, style, defaults),
+        Argument::Empty(x) => optional_vectorization(x, name, style, defaults),
+        Argument::CollectionText(x) => optional_vectorization(x, name, style, defaults),
+        Argument::CollectionPathPattern(x) => optional_vectorization(x, name, style, defaults),
+    }
 }
-fn optional_vectorization<T: Vectorize>(v: Option<T>, name: &Name, defaults: &Argument) -> Vec<String> {
+fn optional_vectorization<T: Vectorize>(v: Option<T>, name: &Name, style: ValueStyle, defaults: &Argument) -> Result<Vec<String>, ErrorKind> {
     match v {
-        Some(n) => n.vec(name),
+        Some(n) => n.vec(name, style),
         None => match defaults {
-            Argument::Empty(Some(())) => Vec::new(), //Skipped
-            Argument::Empty(None) => panic!("Mandatory argument {} was not provided", name),
-            _ => Entry {
-                defaults_to: DefaultValue::Mandatory,
-                source: SourceFormatter::Default,
-                target_name: name.clone(),
-                target_type: defaults.clone(),
-            }.generate(),
+            Argument::Empty(Some(())) => Ok(Vec::new()), //Skipped
+            Argument::Empty(None) => Err(ErrorKind::MissingMandatory),
+            _ => argument_vectorization(defaults.clone(), name, style, &Argument::Empty(None)),
         },
     }
 }
+/// Writes a single value under its name, following the style.
+fn value_vectorization(value: String, name: &Name, style: ValueStyle) -> Result<Vec<String>, ErrorKind> {
+    match (name, style) {
+        (Name::Blank(_), _) => Ok(vec![value]),
+        (Name::Undefined, _) => Err(ErrorKind::UndefinedName),
+        (_, ValueStyle::Separate) => Ok(vec![name.to_string(), value]),
+        (_, ValueStyle::Equals) => Ok(vec![format!("{}={}", name, value)]),
+        (_, ValueStyle::Glued) => Ok(vec![format!("{}{}", name, value)]),
+    }
+}
+/// Named collections repeat their name with each element (-e a -e b), blank ones become consecutive positionals.
 impl<T> Vectorize for Vec<T> 
     where T: Vectorize {
-        fn vec(self, name: &Name) -> Vec<String> { //, name: Name, defaults: DefaultValue
+        fn vec(self, name: &Name, style: ValueStyle) -> Result<Vec<String>, ErrorKind> { //, name: Name, defaults: DefaultValue
             let mut r: Vec<String> = Vec::new();
             for c in self {
-                r.extend(c.vec(name));
+                r.extend(c.vec(name, style)?);
             }
-            return r;
+            return Ok(r);
         }
     }
 
 impl Vectorize for String {
-    fn vec(self, name: &Name) -> Vec<String> {
-        return vec![self];
+    fn vec(self, name: &Name, style: ValueStyle) -> Result<Vec<String>, ErrorKind> {
+        return value_vectorization(self, name, style);
     }
 }
 impl Vectorize for bool {
-    fn vec(self, name: &Name) -> Vec<String> {
-        let n = name.to_string();
-        if n == "" { //Named as a positional argument, which is, bad!
-            panic!("Boolean type was used for positional argument, impossible to generate.");
+    fn vec(self, name: &Name, _style: ValueStyle) -> Result<Vec<String>, ErrorKind> {
+        match name {
+            Name::Blank(_) => return Err(ErrorKind::PositionalFlag), //Named as a positional argument, which is, bad!
+            Name::Undefined => return Err(ErrorKind::UndefinedName),
+            _ => (),
         }
         match self {
-            true => vec![n],
-            false => vec![],
+            true => Ok(vec![name.to_string()]),
+            false => Ok(vec![]),
         }
     }
 }
 impl Vectorize for PathBuf {
-    fn vec(self, name: &Name) -> Vec<String> {
-        return vec![self.display().to_string()];
+    fn vec(self, name: &Name, style: ValueStyle) -> Result<Vec<String>, ErrorKind> {
+        return value_vectorization(self.display().to_string(), name, style);
     }
 }
 impl Vectorize for () {
-    fn vec(self, _name: &Name) -> Vec<String> {
-        return vec![];
+    fn vec(self, _name: &Name, _style: ValueStyle) -> Result<Vec<String>, ErrorKind> {
+        return Ok(vec![]);
     }
 }
 
+/// How a named entry joins its name and value. Positionals, flags and empty entries ignore it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValueStyle {
+    /// Two arguments: --name value, -n value
+    #[default]
+    Separate,
+    /// One argument joined by =: --name=value, -n=value
+    Equals,
+    /// One argument, the value glued right after the name: -nvalue
+    Glued,
+}
+
 #[derive(Clone, Debug)]
 pub enum SourceFormatter {
     /// No formatting
     Default,
-    /// Filter in only certain elements, placeholder for now
-    Filter,
+    /// Runs the value through each filter, in order. See `SourceFormatter::then` to build one.
+    Filter(Vec<Filter>),
 }
 #[derive(Clone, Debug)]
 pub enum DefaultValue {
@@ -249,88 +307,94 @@ pub enum DefaultValue {
 pub struct Entry {
     pub defaults_to: DefaultValue,
     pub source: SourceFormatter,
+    /// The clap field this entry reads from, the `{...}` part of a spec. Entries bound by hand leave it empty.
+    pub source_field: Option<String>,
     pub target_name: Name,
     pub target_type: Argument,
+    pub value_style: ValueStyle,
+}
+/// What went wrong while turning an entry into arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The value read from clap cannot be stored in the entry's target type.
+    UnsupportedTransformation { from: &'static str, to: &'static str },
+    /// A mandatory entry was given no value.
+    MissingMandatory,
+    /// A boolean flag was given a positional name, so there is nothing to write when it is set.
+    PositionalFlag,
+    /// A value had to be written under an undefined name.
+    UndefinedName,
+    /// A formatter filter does not apply to the target type.
+    UnsupportedFilter { filter: &'static str, kind: &'static str },
+    /// `Filter::FileType` does not know this file type.
+    UnknownFileType(String),
+    /// `Filter::Translate` could not write the pattern in the backend's dialect.
+    Translation(TranslateError),
+    /// The entry reads from a field clap does not have.
+    UnknownSource(String),
+}
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::UnsupportedTransformation { from, to } => write!(f, "cannot store a [{}] value in a [{}] argument", from, to),
+            ErrorKind::MissingMandatory => write!(f, "mandatory argument was not provided"),
+            ErrorKind::PositionalFlag => write!(f, "boolean flags cannot be positional"),
+            ErrorKind::UndefinedName => write!(f, "a value cannot be written without a name"),
+            ErrorKind::UnsupportedFilter { filter, kind } => write!(f, "the {} filter does not apply to [{}] arguments", filter, kind),
+            ErrorKind::UnknownFileType(t) => write!(f, "unknown file type `{}`", t),
+            ErrorKind::Translation(e) => write!(f, "{}", e),
+            ErrorKind::UnknownSource(field) => write!(f, "there is no source field `{}`", field),
+        }
+    }
 }
+/// An entry that could not be filled or generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Error {
-
+    /// The failing entry, see `Entry::label`.
+    pub entry: String,
+    pub kind: ErrorKind,
 }
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "entry `{}`: {}", self.entry, self.kind);
+    }
+}
+impl std::error::Error for Error {}
 
 impl PartialEq for Entry {
     fn eq(&self, other: &Self) -> bool {
-        match &self.target_name {
-            //Reminder; we *expect* entries to be diferrent.
-            Name::Blank(i) => match &other.target_name {
-                Name::Blank(j) => i == j,
-                Name::Long(_) => false,
-                Name::Short(_) => false,
-                _ => panic!("Invalid entry!"),
-            },
-            Name::Long(s) => match &other.target_name {
-                Name::Blank(_) => false,
-                Name::Long(t) => s == t,
-                Name::Short(_) => false,
-                _ => panic!("Invalid entry!"),
-            },
-            Name::Short(c) => match &other.target_name {
-                Name::Blank(_) => false,
-                Name::Long(_) => false,
-                Name::Short(d) => c == d,
-                _ => panic!("Invalid entry!"),
-            },
-            _ => panic!("Invalid entry!"),
-        }
+        //Reminder; we *expect* entries to be diferrent.
+        return self.cmp(other) == std::cmp::Ordering::Equal;
     }
 }
 impl Eq for Entry {}
 impl PartialOrd for Entry {
-    /// Conventional order: command <blanks> -<shorts> --<longs>. Why? good question.
+    /// Conventional order: command -<shorts> --<longs> <blanks>. Options come first, as some tools (git grep, BSD grep) stop reading them at the first operand.
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match &self.target_name {
-            //Reminder; we *expect* entries to be diferrent.
-            Name::Blank(i) => match &other.target_name {
-                Name::Blank(j) => i.partial_cmp(j),
-                Name::Long(_) => Some(std::cmp::Ordering::Less),
-                Name::Short(_) => Some(std::cmp::Ordering::Less),
-                _ => None,
-            },
-            Name::Long(s) => match &other.target_name {
-                Name::Blank(_) => Some(std::cmp::Ordering::Greater),
-                Name::Long(t) => s.partial_cmp(t),
-                Name::Short(_) => Some(std::cmp::Ordering::Greater),
-                _ => None,
-            },
-            Name::Short(c) => match &other.target_name {
-                Name::Blank(_) => Some(std::cmp::Ordering::Greater),
-                Name::Long(_) => Some(std::cmp::Ordering::Less),
-                Name::Short(d) => c.partial_cmp(d),
-                _ => None,
-            },
-            _ => None,
-        }
+        return Some(self.cmp(other));
     }
 }
 impl Ord for Entry {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         //We treat undefined like infinity. It's not a problem if they are equal then, as we expect undefined to get ignored.
-        //! THis implementation poses a problem for argument joining! to keep in mind.
-        match &self.target_name {
-            Name::Blank(i) => match &other.target_name {
-                Name::Blank(j) => i.cmp(j),
+        //Entries sharing a name are told apart by their source field, so that several fields may feed the same option (-e a -e b).
+        let by_name = match &self.target_name {
+            Name::Short(c) => match &other.target_name {
+                Name::Short(d) => c.cmp(d),
                 Name::Long(_) => std::cmp::Ordering::Less,
-                Name::Short(_) => std::cmp::Ordering::Less,
+                Name::Blank(_) => std::cmp::Ordering::Less,
                 Name::Undefined => std::cmp::Ordering::Less,
             },
             Name::Long(s) => match &other.target_name {
-                Name::Blank(_) => std::cmp::Ordering::Greater,
-                Name::Long(t) => s.cmp(t),
                 Name::Short(_) => std::cmp::Ordering::Greater,
+                Name::Long(t) => s.cmp(t),
+                Name::Blank(_) => std::cmp::Ordering::Less,
                 Name::Undefined => std::cmp::Ordering::Less,
             },
-            Name::Short(c) => match &other.target_name {
-                Name::Blank(_) => std::cmp::Ordering::Greater,
-                Name::Long(_) => std::cmp::Ordering::Less,
-                Name::Short(d) => c.cmp(d),
+            Name::Blank(i) => match &other.target_name {
+                Name::Short(_) => std::cmp::Ordering::Greater,
+                Name::Long(_) => std::cmp::Ordering::Greater,
+                Name::Blank(j) => i.cmp(j),
                 Name::Undefined => std::cmp::Ordering::Less,
             },
             Name::Undefined => match &other.target_name {
@@ -339,65 +403,197 @@ impl Ord for Entry {
                 Name::Short(_) => std::cmp::Ordering::Greater,
                 Name::Undefined => std::cmp::Ordering::Equal,
             },
+        };
+        return by_name.then_with(|| self.source_field.cmp(&other.source_field));
+    }
+}
+
+/// A malformed entry spec, as reported by `Entry::try_from`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecError {
+    /// The spec that failed to parse.
+    pub spec: String,
+    /// Byte offset of the offending token inside the spec.
+    pub position: usize,
+    /// The offending token. Empty if the spec ended too early.
+    pub token: String,
+    /// What the parser wanted to read at that position.
+    pub expected: &'static str,
+}
+impl Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.token.is_empty() {
+            return write!(f, "invalid entry spec `{}`: expected {} at position {}, found end of spec", self.spec, self.expected, self.position);
         }
+        return write!(f, "invalid entry spec `{}`: expected {} at position {}, found `{}`", self.spec, self.expected, self.position, self.token);
     }
 }
+impl std::error::Error for SpecError {}
+
+/// The token starting at the beginning of `rest`, up to the next section delimiter.
+fn spec_token(rest: &str) -> &str {
+    let end = rest.char_indices().skip(1).find(|(_, c)| "#!<>{}[]=+".contains(*c) || c.is_whitespace()).map_or(rest.len(), |(i, _)| i);
+    return &rest[..end];
+}
 
 impl TryFrom<&str> for Entry {
-    type Error = Error;
+    type Error = SpecError;
     /// Formatter for args, defined as:
     ///   With d the default flag, d ∈ {λ,!,<?*>}
     ///     Where: 
     ///       - λ designs an empty string,
     ///       - ! the ! symbol, 
-    ///       - and <?*> any string, surrounded by the symbols < and >.
+    ///       - and <?*> any string without < or >, surrounded by the symbols < and >.
     ///     Corresponds to:
     ///       - λ => DefaultValue::Skip
     ///       - ! => DefaultValue::Mandatory
-    ///       - <?*> => DefaultValue::Default(?*)
+    ///       - <?*> => DefaultValue::Default(?*), ?* being read as the target type.
+    ///                 Collections split ?* on commas, booleans want true or false, and empty wants nothing at all.
     ///
-    ///   With n the target name, n ∈ {#?i, -?, --?*}
+    ///   With n the target name, n ∈ {#?i, -?, --?*, _}
     ///     Where: 
-    ///       - #?i designs the # symbol followed by any number, 
-    ///       - -? the - symbol followed by any character, 
-    ///       - and --?* the -- symbol followed by any string.
+    ///       - #?i designs the # symbol followed by any number from 0 to 255, 
+    ///       - -? the - symbol followed by any letter or digit, 
+    ///       - --?* the -- symbol followed by any string of letters, digits and -,
+    ///       - and _ the _ symbol.
     ///     Corresponds to:
     ///       - #?i => Name::Blank(?i)
     ///       - -? => Name::Short(?)
     ///       - --?* => Name::Long(?*)
-    ///   With t the target type (or kind), t ∈ {λ} ∪ [.S.] and S = {str}
+    ///       - _ => Name::Undefined
+    ///   With j the value style, j ∈ {λ, =, +}, only allowed after a -? or --?* name.
+    ///     Corresponds to:
+    ///       - λ => ValueStyle::Separate, i.e. --name value
+    ///       - = => ValueStyle::Equals, i.e. --name=value
+    ///       - + => ValueStyle::Glued, i.e. -nvalue
+    ///   With t the target type (or kind), t ∈ {λ} ∪ [.S.] and S = {str, path, str*, path*, bool, empty}
     ///     λ corresponds to a boolean flag
     ///     Corresponds to:
     ///      - str => Option<String>
     ///      - path => Option<PathBuff>
+    ///      - str* => Option<Vec<String>>
+    ///      - path* => Option<Vec<PathBuf>>
+    ///      - bool => Option<bool>
+    ///      - empty => Option<()>
     /// 
-    ///   With s = {_} where _ is any string.
+    ///   With s = {_} where _ is any identifier.
     ///     Corresponds to the source item, as read by clap.
     /// 
-    /// A format as: ndst
+    /// A format as: njdst
     ///   
     /// Examples: #1<->{path}[path]
     ///           -i{casei}
     ///           #0!{pattern}[str]
+    ///           --include={globs}[str*]
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let r = Regex::new(r"((?P<n1>#\d{1,3})|(?P<n2>-\pL)|(?P<n3>--\pL+))((?P<d1>!)|(?P<d2><[\pL-]*>)|(?P<d3>))(?P<s>\{\pL+\})((?P<t1>\[\pL+\])|(?P<t2>))").unwrap();
-        let c = r.captures(value);
+        let fail = |at: usize, expected: &'static str| SpecError {
+            spec: value.to_string(),
+            position: at,
+            token: spec_token(&value[at..]).to_string(),
+            expected,
+        };
+        let name = Regex::new(r"^(#(?P<blank>\d{1,3})|--(?P<long>[\pL\d][\pL\d-]*)|-(?P<short>[\pL\d])|(?P<undefined>_))").unwrap();
+        let default = Regex::new(r"^((?P<mandatory>!)|<(?P<default>[^<>]*)>)?").unwrap();
+        let source = Regex::new(r"^\{(?P<field>[\pL_][\pL\d_]*)\}").unwrap();
+        let kind = Regex::new(r"^(\[(?P<kind>[^\[\]]*)\])?").unwrap();
+
+        let mut at = 0;
+        let c = name.captures(value).ok_or_else(|| fail(at, "a name (#<position>, -<short>, --<long> or _)"))?;
+        let target_name = if let Some(i) = c.name("blank") {
+            Name::Blank(i.as_str().parse().map_err(|_| fail(at, "a position between 0 and 255"))?)
+        } else if let Some(l) = c.name("long") {
+            Name::Long(l.as_str().to_string())
+        } else if let Some(s) = c.name("short") {
+            Name::Short(s.as_str().chars().next().unwrap())
+        } else {
+            Name::Undefined
+        };
+        at += c[0].len();
+
+        let value_style = match value[at..].chars().next() {
+            Some('=') => ValueStyle::Equals,
+            Some('+') => ValueStyle::Glued,
+            _ => ValueStyle::Separate,
+        };
+        if value_style != ValueStyle::Separate {
+            if let Name::Blank(_) | Name::Undefined = target_name {
+                return Err(fail(at, "a default or source field, positional and undefined names take no value style"));
+            }
+            at += 1;
+        }
 
+        let c = default.captures(&value[at..]).unwrap();
+        let default_at = at;
+        let default_text = c.name("default").map(|d| d.as_str());
+        let mandatory = c.name("mandatory").is_some();
+        at += c[0].len();
+        if value[at..].starts_with('<') {
+            return Err(fail(at, "a default value (<...>) without nested < or >"));
+        }
 
+        let c = source.captures(&value[at..]).ok_or_else(|| fail(at, "a source field ({<field>})"))?;
+        let source_field = c["field"].to_string();
+        at += c[0].len();
 
-        return Err(Error{});
+        let c = kind.captures(&value[at..]).unwrap();
+        let kind_at = at;
+        let target_type = match c.name("kind").map(|k| k.as_str()) {
+            None | Some("bool") => Argument::BooleanFlag(None),
+            Some("str") => Argument::Text(None),
+            Some("path") => Argument::PathPattern(None),
+            Some("str*") => Argument::CollectionText(None),
+            Some("path*") => Argument::CollectionPathPattern(None),
+            Some("empty") => Argument::Empty(None),
+            Some(_) => return Err(fail(kind_at, "a type ([str], [path], [str*], [path*], [bool] or [empty])")),
+        };
+        at += c[0].len();
+        if value[at..].starts_with('[') {
+            return Err(fail(at, "a type ([str], [path], [str*], [path*], [bool] or [empty])"));
+        }
+        if at != value.len() {
+            return Err(fail(at, "the end of the spec"));
+        }
+
+        let defaults_to = match default_text {
+            Some(d) => DefaultValue::Default(match target_type {
+                Argument::Text(_) => Argument::Text(Some(d.to_string())),
+                Argument::PathPattern(_) => Argument::PathPattern(Some(PathBuf::from(d))),
+                Argument::CollectionText(_) => Argument::CollectionText(Some(d.split(',').map(str::to_string).collect())),
+                Argument::CollectionPathPattern(_) => Argument::CollectionPathPattern(Some(d.split(',').map(PathBuf::from).collect())),
+                Argument::BooleanFlag(_) => Argument::BooleanFlag(Some(d.parse().map_err(|_| fail(default_at, "a boolean default (<true> or <false>)"))?)),
+                Argument::Empty(_) if d.is_empty() => Argument::Empty(Some(())),
+                Argument::Empty(_) => return Err(fail(default_at, "an empty default (<>)")),
+            }),
+            None if mandatory => DefaultValue::Mandatory,
+            None => DefaultValue::Skip,
+        };
+
+        return Ok(Entry {
+            defaults_to,
+            source: SourceFormatter::Default,
+            source_field: Some(source_field),
+            target_name,
+            target_type,
+            value_style,
+        });
     }
 }
 
 pub trait Transformable<U> {
-    /// Takes an empty entry and fills it.
-    fn fill(&mut self, with: &U);
+    /// Takes an empty entry and fills it, running the value through the entry's formatter.
+    fn fill(&mut self, with: &U) -> Result<(), Error>;
 
 }
 impl<U> Transformable<U> for Entry
 where Argument: Transform<U> {
-    fn fill(&mut self, with: &U) {
-        self.target_type.transform(with);
+    fn fill(&mut self, with: &U) -> Result<(), Error> {
+        let mut value = self.target_type.clone();
+        let r = value.transform(with).and_then(|_| self.source.format(value));
+        match r {
+            Ok(v) => self.target_type = v,
+            Err(kind) => return Err(Error { entry: self.label(), kind }),
+        }
+        return Ok(());
     }
     /*fn conform(self) -> Vec<String> {
         let mut replacement = self.clone();
@@ -429,18 +625,114 @@ impl Entry {
         return Entry {
             defaults_to: DefaultValue::Skip,
             source: SourceFormatter::Default,
+            source_field: None,
             target_name: Name::Undefined,
             target_type: Argument::Empty(None),
+            value_style: ValueStyle::Separate,
         };
     }
-    pub fn transform(self) -> Vec<String> {
+    pub fn transform(self) -> Result<Vec<String>, Error> {
         return self.generate();
     }
+    /// How the entry is called in error messages: its source field, or its name when it has none.
+    pub fn label(&self) -> String {
+        match (&self.source_field, &self.target_name) {
+            (Some(field), _) => field.clone(),
+            (None, Name::Blank(i)) => format!("#{}", i),
+            (None, Name::Undefined) => "_".to_string(),
+            (None, name) => name.to_string(),
+        }
+    }
+    /// Parses a whole argument table, one spec per item. See `Entry::try_from` for the syntax.
+    pub fn table<'a, I: IntoIterator<Item = &'a str>>(specs: I) -> Result<Vec<Entry>, SpecError> {
+        return specs.into_iter().map(Entry::try_from).collect();
+    }
 }
 
 pub trait Convertible<T> {
     /// Polulate entry with clap data, returns the ordered entry bundle
-    fn populate(&mut self, with: T) -> BTreeSet<Entry>;
+    fn populate(&mut self, with: T) -> Result<BTreeSet<Entry>, Error>;
     /// Takes clap data, and converts it to a command string.
-    fn generate(with: BTreeSet<Entry>) -> Vec<String>;
-}
\ No newline at end of file
+    fn generate(with: BTreeSet<Entry>) -> Result<Vec<String>, Error>;
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_table() {
+        let t = Entry::table(["#0!{pattern}[str]", "-i{casei}", "#1<->{path}[path]", "--glob<*.rs,*.toml>{globs}[str*]", "_{unused}[empty]"]).unwrap();
+        assert!(matches!(&t[0].target_name, Name::Blank(0)));
+        assert!(matches!(t[0].defaults_to, DefaultValue::Mandatory));
+        assert!(matches!(t[0].target_type, Argument::Text(None)));
+        assert_eq!(t[0].source_field.as_deref(), Some("pattern"));
+
+        assert!(matches!(&t[1].target_name, Name::Short('i')));
+        assert!(matches!(t[1].defaults_to, DefaultValue::Skip));
+        assert!(matches!(t[1].target_type, Argument::BooleanFlag(None)));
+
+        assert!(matches!(&t[2].defaults_to, DefaultValue::Default(Argument::PathPattern(Some(p))) if p == &PathBuf::from("-")));
+
+        assert!(matches!(&t[3].target_name, Name::Long(l) if l == "glob"));
+        assert!(matches!(&t[3].defaults_to, DefaultValue::Default(Argument::CollectionText(Some(v))) if v == &["*.rs", "*.toml"]));
+
+        assert!(matches!(&t[4].target_name, Name::Undefined));
+        assert!(matches!(t[4].target_type, Argument::Empty(None)));
+    }
+
+    #[test]
+    fn spec_errors() {
+        let e = Entry::try_from("#0!{pattern}[string]").unwrap_err();
+        assert_eq!((e.position, e.token.as_str()), (12, "[string"));
+        let e = Entry::try_from("-i<maybe>{casei}").unwrap_err();
+        assert_eq!((e.position, e.token.as_str()), (2, "<maybe"));
+        let e = Entry::try_from("#300{path}[path]").unwrap_err();
+        assert_eq!((e.position, e.token.as_str()), (0, "#300"));
+        let e = Entry::try_from("--exclude").unwrap_err();
+        assert_eq!((e.position, e.token.as_str()), (9, ""));
+        let e = Entry::try_from("=i{casei}").unwrap_err();
+        assert_eq!((e.position, e.token.as_str()), (0, "=i"));
+    }
+
+    #[test]
+    fn generation_errors() {
+        let mut e = Entry::try_from("#0{flag}").unwrap();
+        e.fill(&true).unwrap();
+        assert_eq!(e.generate(), Err(Error { entry: "flag".to_string(), kind: ErrorKind::PositionalFlag }));
+
+        let e = Entry::try_from("#0!{pattern}[str]").unwrap();
+        assert_eq!(e.generate().unwrap_err().kind, ErrorKind::MissingMandatory);
+
+        let mut e = Entry::try_from("-i{casei}").unwrap();
+        let err = e.fill(&"text".to_string()).unwrap_err();
+        assert_eq!(err.to_string(), "entry `casei`: cannot store a [str] value in a [bool] argument");
+    }
+
+    #[test]
+    fn collections() {
+        let mut e = Entry::try_from("-e!{patterns}[str*]").unwrap();
+        e.fill(&vec!["a".to_string(), "b".to_string()]).unwrap();
+        assert_eq!(e.generate().unwrap(), ["-e", "a", "-e", "b"]);
+
+        let mut e = Entry::try_from("#1{paths}[path*]").unwrap();
+        e.fill(&vec![PathBuf::from("src"), PathBuf::from("vendor")]).unwrap();
+        assert_eq!(e.generate().unwrap(), ["src", "vendor"]);
+
+        let mut e = Entry::try_from("#1<.>{paths}[path*]").unwrap();
+        e.fill(&Vec::<PathBuf>::new()).unwrap();
+        assert_eq!(e.generate().unwrap(), ["."]);
+    }
+
+    #[test]
+    fn value_styles() {
+        let styled = |spec: &str| {
+            let mut e = Entry::try_from(spec).unwrap();
+            e.fill(&vec!["*.rs".to_string(), "*.md".to_string()]).unwrap();
+            return e.generate().unwrap();
+        };
+        assert_eq!(styled("--glob{globs}[str*]"), ["--glob", "*.rs", "--glob", "*.md"]);
+        assert_eq!(styled("--include={globs}[str*]"), ["--include=*.rs", "--include=*.md"]);
+        assert_eq!(styled("-g+{globs}[str*]"), ["-g*.rs", "-g*.md"]);
+        assert_eq!(Entry::try_from("#0={globs}[str*]").unwrap_err().position, 2);
+    }
+}
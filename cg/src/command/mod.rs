@@ -1,19 +1,152 @@
+pub mod capabilities;
+pub mod config;
+pub mod dialect;
+pub mod files;
+pub mod formatter;
 pub mod framework;
+pub mod native;
+pub mod shell;
 pub mod tools;
 
-use clap::{Parser, command};
+use clap::Parser;
 use std::path::PathBuf;
+use std::time::Duration;
+use framework::{Entry, Error, ErrorKind, Transformable};
+use crate::export;
+use crate::matches::Color;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author = "SliceOfArdath", version, about = "Find code, fast.", long_about = None)]
 pub struct Args {
     /// The regular expression used for searching.
     #[arg(required=true,value_name="PATTERN")]
     regex_pattern: String,
-    /// The file or directory to search.
+    /// More regular expressions, searched along with the first one.
+    #[arg(short='e', long="regexp", value_name="PATTERN")]
+    patterns: Vec<String>,
+    /// The files or directories to search. - is standard input, which is also searched when nothing else is and input is piped in.
     #[arg(value_name="PATH")]
-    file: Option<PathBuf>,
+    file: Vec<PathBuf>,
+    /// Only search files of this type, i.e. rust or py.
+    #[arg(short='t', long="type", value_name="TYPE")]
+    types: Vec<String>,
     /// Case insensitive mode
     #[arg(short='i')]
     casei: bool,
-}
\ No newline at end of file
+    /// Let matches span several lines.
+    #[arg(short='U', long)]
+    multiline: bool,
+    /// Skip files ignored by git.
+    #[arg(long)]
+    gitignore: bool,
+    /// Show the column of each match.
+    #[arg(long)]
+    column: bool,
+    /// When to colour results: auto, always or never. Auto colours terminals, unless NO_COLOR is set.
+    #[arg(long, value_name="WHEN", default_value_t)]
+    color: Color,
+    /// Print results for an editor instead of listing them: vim, emacs or vscode.
+    #[arg(long, value_name="FORMAT")]
+    export: Option<export::Format>,
+    /// Stop searching after this many seconds. Results found until then are kept.
+    #[arg(long, value_name="SECONDS", value_parser=parse_timeout)]
+    timeout: Option<Duration>,
+    /// Tell what is run, and in which environment.
+    #[arg(long)]
+    verbose: bool,
+    /// Print the backend command lines instead of running them, quoted for sh, bash, fish or powershell.
+    #[arg(long, value_name="SHELL", num_args=0..=1, require_equals=true, default_missing_value="sh")]
+    print_command: Option<shell::Shell>,
+    /// The search tool to run, i.e. rg, grep or native. Defaults to the first one installed, see config.toml.
+    #[arg(long, value_name="NAME")]
+    backend: Option<String>,
+}
+
+impl Args {
+    /// The fields entries may read from, as written between braces in a spec.
+    pub const FIELDS: &'static [&'static str] = &["regex_pattern", "patterns", "file", "types", "casei", "multiline", "gitignore", "column"];
+
+    /// The path that stands for standard input.
+    pub const STDIN: &'static str = "-";
+
+    /// The backend asked for on the command line, if any.
+    pub fn backend(&self) -> Option<&str> {
+        return self.backend.as_deref();
+    }
+
+    /// How long the search may take, if limited.
+    pub fn timeout(&self) -> Option<Duration> {
+        return self.timeout;
+    }
+
+    /// Whether results should show their column.
+    pub fn column(&self) -> bool {
+        return self.column;
+    }
+
+    /// When to colour results.
+    pub fn color(&self) -> Color {
+        return self.color;
+    }
+
+    /// The format to print results in for an editor, instead of listing them.
+    pub fn export(&self) -> Option<export::Format> {
+        return self.export;
+    }
+
+    /// Whether to tell what is run.
+    pub fn verbose(&self) -> bool {
+        return self.verbose;
+    }
+
+    /// The shell to print command lines for, when not running them.
+    pub fn print_command(&self) -> Option<shell::Shell> {
+        return self.print_command;
+    }
+
+    /// The patterns searched for, first one first.
+    pub fn all_patterns(&self) -> Vec<String> {
+        return std::iter::once(&self.regex_pattern).chain(self.patterns.iter()).cloned().collect();
+    }
+
+    /// Whether standard input is among the roots.
+    pub fn reads_stdin(&self) -> bool {
+        return self.file.iter().any(|f| f.as_os_str() == Self::STDIN);
+    }
+
+    /// Searches standard input, unless some path was given.
+    pub fn default_to_stdin(&mut self) {
+        if self.file.is_empty() {
+            self.file.push(PathBuf::from(Self::STDIN));
+        }
+    }
+
+    /// One search per root, for running them side by side. Without several roots, just this one.
+    pub fn split(&self) -> Vec<Args> {
+        if self.file.len() < 2 {
+            return vec![self.clone()];
+        }
+        return self.file.iter().map(|f| Args { file: vec![f.clone()], ..self.clone() }).collect();
+    }
+
+    /// Fills an entry with the field it reads from.
+    pub fn fill(&self, entry: &mut Entry) -> Result<(), Error> {
+        match entry.source_field.as_deref() {
+            Some("regex_pattern") => entry.fill(&self.regex_pattern),
+            Some("patterns") => entry.fill(&self.patterns),
+            Some("file") => entry.fill(&self.file),
+            Some("types") => entry.fill(&self.types),
+            Some("casei") => entry.fill(&self.casei),
+            Some("multiline") => entry.fill(&self.multiline),
+            Some("gitignore") => entry.fill(&self.gitignore),
+            Some("column") => entry.fill(&self.column),
+            field => Err(Error { entry: entry.label(), kind: ErrorKind::UnknownSource(field.unwrap_or_default().to_string()) }),
+        }
+    }
+}
+
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    return s.parse::<f64>().ok()
+        .and_then(|t| Duration::try_from_secs_f64(t).ok())
+        .ok_or_else(|| format!("`{}` is not a number of seconds", s));
+}
@@ -0,0 +1,254 @@
+//! The search cg runs by itself, with the regex crate, for when no search tool is installed.
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use regex::bytes::{Regex, RegexBuilder};
+use super::capabilities::Feature;
+use super::files;
+use super::formatter;
+use super::tools::{EnvironmentRules, ExitCodes, Grepper, Output};
+use crate::matches::{Match, STDIN_PATH};
+use crate::pipeline::Cancel;
+use super::Args;
+
+/// The name the native backend goes by.
+pub const NAME: &str = "native";
+
+/// How much of a file is looked at to tell binary files apart, as grep does.
+const BINARY_PROBE: usize = 8192;
+/// How often a search waiting on standard input checks whether it was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+/// How many lines of standard input are read ahead of the search.
+const LINES_AHEAD: usize = 1024;
+
+/// The definition of the native backend. It has no command, see `Grepper::is_native`.
+/// .gitignore handling is left to emulation.
+pub fn definition() -> Grepper {
+    return Grepper {
+        name: NAME.to_string(),
+        command: String::new(),
+        implicit: vec![],
+        entries: vec![],
+        capabilities: [Feature::CaseInsensitive, Feature::MultiplePatterns, Feature::FileTypes, Feature::Column, Feature::Multiline, Feature::Stdin].into_iter().collect(),
+        unsupported: Default::default(),
+        exit: ExitCodes::default(),
+        environment: EnvironmentRules::default(),
+        output: Output::default(),
+    };
+}
+
+/// The single regex searching for every pattern at once. Also used to find spans in the results of other backends.
+pub fn regex(args: &Args) -> Result<Regex, regex::Error> {
+    let patterns: Vec<&String> = std::iter::once(&args.regex_pattern).chain(args.patterns.iter()).collect();
+    //Each one alone first, so that errors show the pattern as it was written.
+    for p in &patterns {
+        Regex::new(p)?;
+    }
+    let all: Vec<String> = patterns.iter().map(|p| format!("(?:{})", p)).collect();
+    return RegexBuilder::new(&all.join("|")).case_insensitive(args.casei).multi_line(true).build();
+}
+
+/// The files to search, and whether their paths should lose the ./ they were found under.
+fn targets(args: &Args) -> io::Result<(Vec<PathBuf>, bool)> {
+    let mut globs = Vec::new();
+    for t in &args.types {
+        globs.extend(formatter::globs(t).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?);
+    }
+    let roots: Vec<PathBuf> = args.file.iter().filter(|f| f.as_os_str() != Args::STDIN).cloned().collect();
+    if roots.is_empty() {
+        //Standard input alone is not a reason to search the current directory.
+        if args.reads_stdin() {
+            return Ok((Vec::new(), false));
+        }
+        return Ok((files::walk(&[PathBuf::from(".")], &globs)?, true));
+    }
+    return Ok((files::walk(&roots, &globs)?, false));
+}
+
+/// Searches the files of `args`, handing each match to `emit`. Files that cannot be read are reported on stderr and skipped.
+/// Once `cancel` is triggered, no other file is searched. Returns whether anything matched.
+pub fn search(args: &Args, emit: &mut impl FnMut(Match) -> io::Result<()>, cancel: &Cancel) -> io::Result<bool> {
+    let re = regex(args).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let (paths, strip) = targets(args)?;
+    let mut found = false;
+    if args.reads_stdin() {
+        found |= search_stdin(&re, args, BufReader::new(io::stdin()), emit, cancel)?;
+    }
+    for p in paths {
+        if cancel.reason().is_some() {
+            break;
+        }
+        let shown = if strip { p.strip_prefix(".").unwrap_or(&p) } else { &p };
+        match fs::read(&p) {
+            Ok(content) => found |= search_file(&re, args, shown, &content, emit)?,
+            Err(e) => eprintln!("cg: {}: {}", p.display(), e),
+        }
+    }
+    return Ok(found);
+}
+
+/// Reads lines on a thread of its own, so that whoever waits for them can give up while the input is idle. The thread
+/// is left behind then, blocked on its read until the input ends or cg exits.
+fn lines(mut from: impl BufRead + Send + 'static) -> Receiver<io::Result<Vec<u8>>> {
+    let (tx, rx) = mpsc::sync_channel(LINES_AHEAD);
+    thread::spawn(move || loop {
+        let mut line = Vec::new();
+        match from.read_until(b'\n', &mut line) {
+            Ok(0) => return,
+            Ok(_) => if tx.send(Ok(line)).is_err() {
+                return;
+            },
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                return;
+            },
+        }
+    });
+    return rx;
+}
+
+/// Searches standard input. Lines are searched as they come, so that endless input, i.e. a log being followed, gives
+/// results right away. Multiline searches need all of it first. Once `cancel` is triggered, the search stops, even
+/// while waiting for input.
+fn search_stdin(re: &Regex, args: &Args, from: impl BufRead + Send + 'static, emit: &mut impl FnMut(Match) -> io::Result<()>, cancel: &Cancel) -> io::Result<bool> {
+    let path = Path::new(STDIN_PATH);
+    let rx = lines(from);
+    let next = || loop {
+        if cancel.reason().is_some() {
+            return None;
+        }
+        match rx.recv_timeout(CANCEL_POLL) {
+            Ok(line) => return Some(line),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    };
+    if args.multiline {
+        let mut content = Vec::new();
+        while let Some(line) = next() {
+            content.extend(line?);
+        }
+        if cancel.reason().is_some() {
+            return Ok(false);
+        }
+        return search_file(re, args, path, &content, emit);
+    }
+    let mut found = false;
+    let mut offset = 0;
+    for number in 1.. {
+        let Some(line) = next() else { break };
+        let line = line?;
+        let start = offset;
+        offset += line.len();
+        if line.contains(&0) {
+            continue;
+        }
+        let mut results = Vec::new();
+        search_file(re, args, path, &line, &mut |m| {
+            results.push(m);
+            return Ok(());
+        })?;
+        for mut m in results {
+            m.line = number;
+            m.offset = m.offset.map(|o| o + start);
+            emit(m)?;
+            found = true;
+        }
+    }
+    return Ok(found);
+}
+
+/// Searches a single file's content. Binary files are skipped.
+/// Lines with several matches come out once, with a span for each. A multiline match gives a result for each of its lines.
+fn search_file(re: &Regex, args: &Args, path: &Path, content: &[u8], emit: &mut impl FnMut(Match) -> io::Result<()>) -> io::Result<bool> {
+    if content[..content.len().min(BINARY_PROBE)].contains(&0) {
+        return Ok(false);
+    }
+    //Where each line starts, to turn offsets into line numbers.
+    let starts: Vec<usize> = std::iter::once(0).chain(content.iter().enumerate().filter(|(_, b)| **b == b'\n').map(|(i, _)| i + 1)).collect();
+    let line_of = |offset: usize| starts.partition_point(|s| *s <= offset) - 1;
+    //Where each line starts and ends, line break excluded.
+    let bounds = |line: usize| {
+        let end = starts.get(line + 1).map(|e| e - 1).unwrap_or(content.len()).max(starts[line]);
+        return (starts[line], if content[starts[line]..end].ends_with(b"\r") { end - 1 } else { end });
+    };
+
+    //Without -U, each line is searched alone, so that $ and \s stop at its end. A trailing line break ends the last line.
+    let matches: Vec<(usize, usize)> = if args.multiline {
+        re.find_iter(content).map(|m| (m.start(), m.end())).collect()
+    } else {
+        let lines = if content.ends_with(b"\n") || content.is_empty() { starts.len() - 1 } else { starts.len() };
+        (0..lines).flat_map(|l| {
+            let (start, end) = bounds(l);
+            return re.find_iter(&content[start..end]).map(move |m| (start + m.start(), start + m.end()));
+        }).collect()
+    };
+
+    let mut current: Option<Match> = None;
+    let mut found = false;
+    for (m_start, m_end) in matches {
+        found = true;
+        let first = line_of(m_start);
+        let last = if args.multiline { line_of(m_end.saturating_sub(1).max(m_start)) } else { first };
+        for line in first..=last {
+            let (start, end) = bounds(line);
+            let span = m_start.clamp(start, end) - start..m_end.clamp(start, end) - start;
+            match current.as_mut() {
+                Some(c) if c.line == line + 1 => c.spans.push(span),
+                _ => {
+                    if let Some(c) = current.take() {
+                        emit(c)?;
+                    }
+                    let mut next = Match::new(path.to_path_buf(), line + 1, String::from_utf8_lossy(&content[start..end]).into_owned());
+                    next.column = Some(span.start + 1);
+                    next.offset = Some(start + span.start);
+                    next.spans.push(span);
+                    current = Some(next);
+                },
+            }
+        }
+    }
+    if let Some(c) = current {
+        emit(c)?;
+    }
+    return Ok(found);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn run(argv: &[&str], content: &str) -> String {
+        let args = Args::parse_from(argv);
+        let mut out = Vec::new();
+        search_file(&regex(&args).unwrap(), &args, Path::new("f"), content.as_bytes(), &mut |m| {
+            out.push(m.render(args.column));
+            return Ok(());
+        }).unwrap();
+        return out.iter().map(|l| format!("{}\n", l)).collect();
+    }
+
+    #[test]
+    fn results() {
+        let content = "fn a() {}\nlet b = 1;\nFN c() { fn d() }\n";
+        assert_eq!(run(&["cg", r"fn \w"], content), "f:1:fn a() {}\nf:3:FN c() { fn d() }\n");
+        assert_eq!(run(&["cg", "b", "-e", "^fn", "-i", "--column"], content), "f:1:1:fn a() {}\nf:2:5:let b = 1;\nf:3:1:FN c() { fn d() }\n");
+        assert_eq!(run(&["cg", r"\{\}\nlet"], content), "");
+        assert_eq!(run(&["cg", r"\{\}\nlet", "-U"], content), "f:1:fn a() {}\nf:2:let b = 1;\n");
+        assert_eq!(run(&["cg", "a"], "a\0b"), "");
+        assert_eq!(run(&["cg", "foo[^;]*"], "foo\nbar;\na\nb"), "f:1:foo\n");
+        assert_eq!(run(&["cg", r"a\s*"], "foo\nbar;\na\nb"), "f:2:bar;\nf:3:a\n");
+
+        let args = Args::parse_from(["cg", "b", "-"]);
+        let mut out = Vec::new();
+        search_stdin(&regex(&args).unwrap(), &args, &b"a\nab\n\0b\nb"[..], &mut |m| {
+            out.push((m.render(false), m.offset));
+            return Ok(());
+        }, &Cancel::new()).unwrap();
+        assert_eq!(out, [("<stdin>:2:ab".to_string(), Some(3)), ("<stdin>:4:b".to_string(), Some(8))]);
+    }
+}
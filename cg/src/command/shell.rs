@@ -0,0 +1,151 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A shell syntax, to write command lines in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    /// POSIX sh, and everything like it.
+    Sh,
+    /// Like sh, with $'...' for control characters so that they stay on one line.
+    Bash,
+    Fish,
+    PowerShell,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sh" => Ok(Shell::Sh),
+            "bash" | "zsh" => Ok(Shell::Bash),
+            "fish" => Ok(Shell::Fish),
+            "powershell" | "pwsh" => Ok(Shell::PowerShell),
+            _ => Err(format!("unknown shell `{}`, expected sh, bash, fish or powershell", s)),
+        }
+    }
+}
+impl Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shell::Sh => write!(f, "sh"),
+            Shell::Bash => write!(f, "bash"),
+            Shell::Fish => write!(f, "fish"),
+            Shell::PowerShell => write!(f, "powershell"),
+        }
+    }
+}
+
+/// Whether an argument reads the same to every shell without quotes.
+/// Commas and @ are left out for PowerShell, and % for fish.
+fn plain(arg: &str) -> bool {
+    return !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "_-+=./:".contains(c));
+}
+
+/// Quotes an argument, so that the shell hands it over as is.
+pub fn quote(arg: &str, shell: Shell) -> String {
+    if plain(arg) {
+        return arg.to_string();
+    }
+    match shell {
+        Shell::Bash if arg.chars().any(char::is_control) => {
+            let mut r = String::from("$'");
+            for c in arg.chars() {
+                match c {
+                    '\\' => r.push_str("\\\\"),
+                    '\'' => r.push_str("\\'"),
+                    '\n' => r.push_str("\\n"),
+                    '\t' => r.push_str("\\t"),
+                    '\r' => r.push_str("\\r"),
+                    c if c.is_control() => r.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => r.push(c),
+                }
+            }
+            r.push('\'');
+            return r;
+        },
+        Shell::Sh | Shell::Bash => return format!("'{}'", arg.replace('\'', "'\\''")),
+        Shell::Fish => return format!("'{}'", arg.replace('\\', "\\\\").replace('\'', "\\'")),
+        //PowerShell takes typographic single quotes for quotes too.
+        Shell::PowerShell => return format!("'{}'", arg.chars().map(|c| match c {
+            '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' => format!("{}{}", c, c),
+            c => c.to_string(),
+        }).collect::<String>()),
+    }
+}
+
+/// Writes a whole command line, program first.
+pub fn join(line: &[String], shell: Shell) -> String {
+    let words: Vec<String> = line.iter().map(|a| quote(a, shell)).collect();
+    //A quoted program is only a string to PowerShell, unless called.
+    if shell == Shell::PowerShell && line.first().is_some_and(|p| !plain(p)) {
+        return format!("& {}", words.join(" "));
+    }
+    return words.join(" ");
+}
+
+/// Splits a command line into words as sh does, with its quotes and backslashes, but without expanding anything.
+/// None when a quote is left open.
+pub fn split(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            words.extend(word.take());
+            continue;
+        }
+        let w = word.get_or_insert_with(String::new);
+        match c {
+            '\'' => loop {
+                match chars.next()? {
+                    '\'' => break,
+                    c => w.push(c),
+                }
+            },
+            //Within double quotes, a backslash only escapes what would be special there.
+            '"' => loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => match chars.next()? {
+                        c @ ('$' | '`' | '"' | '\\') => w.push(c),
+                        '\n' => (),
+                        c => w.extend(['\\', c]),
+                    },
+                    c => w.push(c),
+                }
+            },
+            '\\' => match chars.next() {
+                Some('\n') => (),
+                Some(c) => w.push(c),
+                None => w.push('\\'),
+            },
+            c => w.push(c),
+        }
+    }
+    words.extend(word);
+    return Some(words);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn quoting() {
+        let tricky = ["plain", "", "it's", "$HOME `x`", "a b\nc\\d", "-e", "x*"];
+        for shell in [Shell::Sh, Shell::Bash] {
+            let line = format!("printf '%s\\0' {}", tricky.iter().map(|a| quote(a, shell)).collect::<Vec<_>>().join(" "));
+            let out = Command::new(shell.to_string()).arg("-c").arg(&line).output().unwrap();
+            assert_eq!(String::from_utf8(out.stdout).unwrap().split('\0').collect::<Vec<_>>()[..tricky.len()], tricky);
+        }
+        assert_eq!(quote("a b\nc", Shell::Bash), "$'a b\\nc'");
+        assert_eq!(quote("it's \\", Shell::Fish), "'it\\'s \\\\'");
+        assert_eq!(quote("it's $x", Shell::PowerShell), "'it''s $x'");
+        assert_eq!(join(&["C:\\Program Files\\rg.exe".to_string(), "-e".to_string()], Shell::PowerShell), "& 'C:\\Program Files\\rg.exe' -e");
+
+        assert_eq!(split(&join(&tricky.map(str::to_string), Shell::Sh)).unwrap(), tricky);
+        assert_eq!(split(r#" "/opt/My Editor/ed" -w  a\ b"\$x\y" '' "#).unwrap(), ["/opt/My Editor/ed", "-w", r"a b$x\y", ""]);
+        assert_eq!(split("ed 'x"), None);
+    }
+}
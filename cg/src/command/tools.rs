@@ -1,68 +1,320 @@
-use super::framework::{Entry, SourceFormatter, Name, Argument, DefaultValue, Convertible, Transformable};
+use super::capabilities::Feature;
+use super::framework::{Entry, Convertible, Error, Name};
+use super::native;
 use super::Args;
-use std::collections::{BTreeSet};
+use crate::matches::Format;
+use crate::pipeline::Environment;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use serde::Deserialize;
 
 
-#[derive(Clone)] 
+/// A search backend, as described by a definition file. See `config` for the format.
+#[derive(Clone, Debug)]
 pub struct Grepper {
-    /// A regular expression used for searching.
-    regex_pattern: Entry,
-    /// A file or directory to search. Directories may be searched recursively.
-    file: Entry,
-    /// Case sensitivity flag
-    casei: Entry,
-}
-
-pub const GREP: Grepper = Grepper {
-    regex_pattern: Entry { 
-        defaults_to: DefaultValue::Mandatory,
-        source: SourceFormatter::Default,
-        target_name: Name::Blank(0),
-        target_type: Argument::Text(None),
-    },
-    file: Entry {
-        defaults_to: DefaultValue::Skip,
-        source: SourceFormatter::Default,
-        target_name: Name::Blank(1),
-        target_type: Argument::PathPattern(None),
-    },
-    casei: Entry::ignore(),/*{
-        defaults_to: DefaultValue::Skip,
-        source: SourceFormatter::Default,
-        target_name: Name::Short('i'),
-        target_type: Argument::BooleanFlag(None),
-    }*/
-};
+    /// The name the backend goes by.
+    pub name: String,
+    /// The program to run. Empty for the native backend, see `is_native`.
+    pub command: String,
+    /// Arguments always given, before the generated ones.
+    pub implicit: Vec<String>,
+    /// The argument table. Args fields without an entry are ignored.
+    pub entries: Vec<Entry>,
+    /// What the backend can do by itself. Anything else asked for is emulated, see `capabilities::plan`.
+    pub capabilities: BTreeSet<Feature>,
+    /// What the backend lacks, and that cannot be emulated for it either. Asking for it is refused.
+    pub unsupported: BTreeSet<Feature>,
+    /// What the backend's exit statuses mean.
+    pub exit: ExitCodes,
+    /// What the backend's environment is made of.
+    pub environment: EnvironmentRules,
+    /// How to get results cg can parse.
+    pub output: Output,
+}
+
+/// How a backend prints results cg can parse.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Output {
+    /// The format of its results.
+    #[serde(default)]
+    pub format: Format,
+    /// The arguments that make it print in that format, given after the implicit ones.
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+/// How a backend's environment is made from cg's, so that user settings such as GREP_OPTIONS cannot change its output.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentRules {
+    /// Start from nothing rather than cg's environment.
+    #[serde(default)]
+    pub clear: bool,
+    /// Variables of cg's environment kept even so.
+    #[serde(default)]
+    pub pass: Vec<String>,
+    /// Variables removed.
+    #[serde(default)]
+    pub unset: Vec<String>,
+    /// Variables set, whatever the rest says.
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+}
+impl EnvironmentRules {
+    /// The environment to run the backend in, given cg's current one.
+    pub fn resolve(&self) -> Environment {
+        let mut r = Environment { clear: self.clear, vars: BTreeMap::new() };
+        if self.clear {
+            for k in &self.pass {
+                if let Some(v) = env::var_os(k) {
+                    r.vars.insert(k.clone(), Some(v.to_string_lossy().into_owned()));
+                }
+            }
+        }
+        for k in &self.unset {
+            r.vars.insert(k.clone(), None);
+        }
+        for (k, v) in &self.set {
+            r.vars.insert(k.clone(), Some(v.clone()));
+        }
+        return r;
+    }
+}
+
+/// The exit statuses of cg, whatever the backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// Something matched.
+    Matched = 0,
+    /// Nothing matched.
+    Unmatched = 1,
+    /// The search could not be run as asked, i.e. a bad option or pattern, or an unreadable file.
+    Error = 2,
+    /// The backend is not installed.
+    Missing = 3,
+    /// Ctrl-C, as shells report it.
+    Interrupted = 130,
+}
+impl Exit {
+    pub fn code(self) -> i32 {
+        return self as i32;
+    }
+}
+
+/// How to read a backend's exit status, as `Exit`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ExitCodes {
+    /// Statuses meaning something matched. Without any result printed, they mean nothing did.
+    #[serde(default = "ExitCodes::default_matched")]
+    pub matched: Vec<i32>,
+    /// Statuses meaning nothing matched.
+    #[serde(default = "ExitCodes::default_unmatched")]
+    pub unmatched: Vec<i32>,
+    /// Regexes which, found in the backend's stderr, mean an error whatever the status.
+    /// For backends that report errors with the status of a failed search.
+    #[serde(default)]
+    pub error_output: Vec<String>,
+}
+impl ExitCodes {
+    fn default_matched() -> Vec<i32> {
+        return vec![0];
+    }
+    fn default_unmatched() -> Vec<i32> {
+        return vec![1];
+    }
+    /// Reads how a search went. `status` is None when the backend was killed; any status not listed is an error.
+    pub fn classify(&self, status: Option<i32>, results: usize, stderr: &str) -> Exit {
+        if self.error_output.iter().any(|p| Regex::new(p).is_ok_and(|r| r.is_match(stderr))) {
+            return Exit::Error;
+        }
+        match status {
+            Some(c) if self.matched.contains(&c) => if results > 0 { Exit::Matched } else { Exit::Unmatched },
+            Some(c) if self.unmatched.contains(&c) => Exit::Unmatched,
+            _ => Exit::Error,
+        }
+    }
+}
+impl Default for ExitCodes {
+    fn default() -> Self {
+        return ExitCodes { matched: ExitCodes::default_matched(), unmatched: ExitCodes::default_unmatched(), error_output: Vec::new() };
+    }
+}
+
+impl Grepper {
+    /// Whether the search runs inside cg, see `native`.
+    pub fn is_native(&self) -> bool {
+        return self.command.is_empty();
+    }
+    /// Where the program lives, if it is installed. The native backend always is.
+    pub fn installed(&self) -> Option<PathBuf> {
+        if self.is_native() {
+            return env::current_exe().ok();
+        }
+        return which(&self.command);
+    }
+    /// The full command line for a search: program, implicit arguments, output flags, then generated ones.
+    /// Positionals come after --, so that patterns and paths starting with - are not taken for options.
+    pub fn command_line(&self, with: Args) -> Result<Vec<String>, Error> {
+        let mut r = vec![self.command.clone()];
+        r.extend(self.implicit.iter().cloned());
+        r.extend(self.output.flags.iter().cloned());
+        r.extend(Grepper::generate(self.clone().populate(with)?)?);
+        return Ok(r);
+    }
+}
 
 impl Convertible<Args> for Grepper {
     /// Yipeee ^-^
-    /// 
+    ///
     /// First we resolve positions,
     ///   Try to know where each element will be.
-    ///   As a rule of thumb, we'd rather want elements with names to be placed later. 
+    ///   As a rule of thumb, we'd rather want elements with names to be placed later.
     ///   Not that this would matter that much, usually!
-    ///   
-    ///   What we do here, is log which arguments have a fixed position, 
+    ///
+    ///   What we do here, is log which arguments have a fixed position,
     ///     then throw all the non-ordered ones after.
     ///   To optimize the whole thing, we generate the arguments in the same time;
-    ///     throw the non-positionals in a vec, and the positionals in a tree. 
-    fn populate(&mut self, with: Args) -> BTreeSet<Entry> {
+    ///     throw the non-positionals in a vec, and the positionals in a tree.
+    fn populate(&mut self, with: Args) -> Result<BTreeSet<Entry>, Error> {
         let mut r: BTreeSet<Entry> = BTreeSet::new();
-        self.regex_pattern.fill(&with.regex_pattern);
-        self.file.fill(&with.file);
-        self.casei.fill(&with.casei);
-        r.insert(self.regex_pattern.clone());
-        r.insert(self.file.clone());
-        r.insert(self.casei.clone());
-
-        return r;
+        for e in self.entries.iter_mut() {
+            with.fill(e)?;
+            r.insert(e.clone());
+        }
+        return Ok(r);
     }
-    fn generate(with: BTreeSet<Entry>) -> Vec<String> {
+    fn generate(with: BTreeSet<Entry>) -> Result<Vec<String>, Error> {
         let mut r: Vec<String> = Vec::new();
-
+        let mut positional = false;
         for i in with {
-            r.extend(i.transform());
+            let blank = matches!(i.target_name, Name::Blank(_));
+            let arguments = i.transform()?;
+            //Entries come ordered, positionals last, so that a single -- ends the options.
+            if blank && !positional && !arguments.is_empty() {
+                r.push("--".to_string());
+                positional = true;
+            }
+            r.extend(arguments);
+        }
+        return Ok(r);
+    }
+}
+
+/// Looks a program up in PATH, as a shell would.
+pub fn which(program: &str) -> Option<PathBuf> {
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        let p = PathBuf::from(program);
+        return if executable(&p) { Some(p) } else { None };
+    }
+    let path = env::var_os("PATH")?;
+    for dir in env::split_paths(&path) {
+        let candidate = dir.join(program);
+        if executable(&candidate) {
+            return Some(candidate);
+        }
+        if cfg!(windows) && executable(&candidate.with_extension("exe")) {
+            return Some(candidate.with_extension("exe"));
+        }
+    }
+    return None;
+}
+
+#[cfg(unix)]
+fn executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    return path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+}
+#[cfg(not(unix))]
+fn executable(path: &Path) -> bool {
+    return path.is_file();
+}
+
+/// Why no backend could be picked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectError {
+    /// No definition goes by this name.
+    Unknown { name: String, known: Vec<String> },
+    /// The backend asked for is not in PATH.
+    NotInstalled { name: String, command: String },
+    /// None of the backends in the preference order is in PATH.
+    NoneInstalled { order: Vec<String> },
+}
+impl Display for SelectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectError::Unknown { name, known } => write!(f, "unknown backend `{}`, expected one of {}", name, known.join(", ")),
+            SelectError::NotInstalled { name, command } => write!(f, "backend `{}` needs `{}`, which is not in PATH", name, command),
+            SelectError::NoneInstalled { order } => write!(f, "none of the backends is installed (tried {})", order.join(", ")),
         }
-        return r;
     }
-}
\ No newline at end of file
+}
+impl std::error::Error for SelectError {}
+
+/// Picks the backend to run: the one asked for, or else the first installed one in preference order,
+/// or else the native one. Names in the order without a definition are skipped.
+pub fn select<'a>(backends: &'a BTreeMap<String, Grepper>, order: &[String], asked: Option<&str>) -> Result<&'a Grepper, SelectError> {
+    if let Some(name) = asked {
+        let g = backends.get(name).ok_or_else(|| SelectError::Unknown { name: name.to_string(), known: backends.keys().cloned().collect() })?;
+        if g.installed().is_none() {
+            return Err(SelectError::NotInstalled { name: g.name.clone(), command: g.command.clone() });
+        }
+        return Ok(g);
+    }
+    return order.iter()
+        .filter_map(|name| backends.get(name))
+        .find(|g| g.installed().is_some())
+        .or_else(|| backends.get(native::NAME).filter(|g| g.is_native()))
+        .ok_or_else(|| SelectError::NoneInstalled { order: order.to_vec() });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment() {
+        let rules = EnvironmentRules { clear: true, pass: vec!["PATH".to_string(), "CG_SURELY_UNSET".to_string()], unset: vec!["GREP_OPTIONS".to_string()],
+            set: [("LC_ALL".to_string(), "C".to_string())].into_iter().collect() };
+        let e = rules.resolve();
+        assert!(e.clear);
+        assert_eq!(e.vars.keys().collect::<Vec<_>>(), ["GREP_OPTIONS", "LC_ALL", "PATH"]);
+        assert_eq!(e.vars["GREP_OPTIONS"], None);
+        assert_eq!(e.vars["LC_ALL"].as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn exit_codes() {
+        let codes = ExitCodes { error_output: vec!["^ERR:".to_string()], ..ExitCodes::default() };
+        assert_eq!(codes.classify(Some(0), 3, ""), Exit::Matched);
+        assert_eq!(codes.classify(Some(0), 0, ""), Exit::Unmatched);
+        assert_eq!(codes.classify(Some(1), 0, ""), Exit::Unmatched);
+        assert_eq!(codes.classify(Some(1), 0, "ERR: bad regex"), Exit::Error);
+        assert_eq!(codes.classify(Some(2), 5, ""), Exit::Error);
+        assert_eq!(codes.classify(None, 5, ""), Exit::Error);
+    }
+
+    fn backend(name: &str, command: &str) -> Grepper {
+        return Grepper { name: name.to_string(), command: command.to_string(), implicit: vec![], entries: vec![], capabilities: BTreeSet::new(), unsupported: BTreeSet::new(), exit: ExitCodes::default(), environment: EnvironmentRules::default(), output: Output::default() };
+    }
+
+    #[test]
+    fn selection() {
+        let backends: BTreeMap<String, Grepper> = [backend("missing", "cg-surely-not-installed"), backend("sh", "sh")]
+            .into_iter().map(|g| (g.name.clone(), g)).collect();
+        let order = vec!["missing".to_string(), "unknown".to_string(), "sh".to_string()];
+        assert_eq!(select(&backends, &order, None).unwrap().name, "sh");
+        assert_eq!(select(&backends, &order, Some("sh")).unwrap().name, "sh");
+        assert!(matches!(select(&backends, &order, Some("missing")), Err(SelectError::NotInstalled { .. })));
+        assert!(matches!(select(&backends, &order, Some("nope")), Err(SelectError::Unknown { .. })));
+        assert!(matches!(select(&backends, &order[..2], None), Err(SelectError::NoneInstalled { .. })));
+
+        let mut backends = backends;
+        backends.insert(native::NAME.to_string(), native::definition());
+        assert_eq!(select(&backends, &order[..2], None).unwrap().name, native::NAME);
+    }
+}
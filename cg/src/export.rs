@@ -0,0 +1,135 @@
+//! Results written for editors to read: vim's quickfix list, emacs' grep buffers and VS Code's problem matchers.
+//!
+//! VS Code reads tasks output through a problem matcher, which has to be told the format, i.e. in tasks.json:
+//!
+//! ```json
+//! "problemMatcher": {
+//!     "owner": "cg",
+//!     "fileLocation": "absolute",
+//!     "pattern": {
+//!         "regexp": "^(.*):(\\d+):(\\d+):(\\d+):(\\d+): (info): (.*)$",
+//!         "file": 1, "line": 2, "column": 3, "endLine": 4, "endColumn": 5, "severity": 6, "message": 7
+//!     }
+//! }
+//! ```
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use crate::cache::ResultSet;
+use crate::matches::{Match, STDIN_PATH};
+
+/// What to export results as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// path:line:column:text, which vim's default errorformat reads. Columns are in bytes, as vim counts them.
+    Vim,
+    /// A grep-mode buffer: a mode line, then path:line:text with the matches highlighted as grep does.
+    Emacs,
+    /// path:line:column:end line:end column: info: text, see the module documentation. Columns are in characters.
+    Vscode,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vim" | "quickfix" => Ok(Format::Vim),
+            "emacs" | "grep-mode" => Ok(Format::Emacs),
+            "vscode" | "code" => Ok(Format::Vscode),
+            _ => Err(format!("unknown export format `{}`, expected vim, emacs or vscode", s)),
+        }
+    }
+}
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Vim => write!(f, "vim"),
+            Format::Emacs => write!(f, "emacs"),
+            Format::Vscode => write!(f, "vscode"),
+        }
+    }
+}
+
+//How grep highlights matches, which emacs turns back into match positions.
+const MATCH_START: &str = "\x1b[01;31m\x1b[K";
+const MATCH_END: &str = "\x1b[m\x1b[K";
+
+impl Format {
+    /// What comes before the results, if anything. `directory` is where relative paths start from.
+    pub fn header(&self, directory: &Path) -> Option<String> {
+        match self {
+            Format::Emacs => Some(format!("-*- mode: grep; default-directory: \"{}/\" -*-", directory.display().to_string().replace('\\', "\\\\").replace('"', "\\\""))),
+            Format::Vim | Format::Vscode => None,
+        }
+    }
+    /// The lines of a result found from `directory`. Vim and VS Code get one per match, so that each can be gone to.
+    /// Emacs gets one per result, relative to the header's directory. Results read from standard input have no file
+    /// to go to, and get none.
+    pub fn lines(&self, directory: &Path, m: &Match) -> Vec<String> {
+        if m.path == Path::new(STDIN_PATH) {
+            return Vec::new();
+        }
+        let path = directory.join(&m.path);
+        //Without spans, an empty one at the column.
+        let start = m.column.unwrap_or(1).saturating_sub(1);
+        let empty = start..start;
+        let spans = if m.spans.is_empty() { std::slice::from_ref(&empty) } else { m.spans.as_slice() };
+        match self {
+            Format::Vim => return spans.iter().map(|s| format!("{}:{}:{}:{}", path.display(), m.line, s.start + 1, m.text)).collect(),
+            Format::Vscode => {
+                //Characters before a byte offset, or the offset itself when it is not on a character boundary.
+                let chars = |b: usize| m.text.get(..b).map_or(b, |t| t.chars().count());
+                return spans.iter().map(|s| format!("{}:{}:{}:{}:{}: info: {}", path.display(), m.line, chars(s.start) + 1, m.line, chars(s.end) + 1, m.text)).collect();
+            },
+            Format::Emacs => {
+                let mut text = String::new();
+                let mut at = 0;
+                for s in &m.spans {
+                    let (Some(before), Some(matched)) = (m.text.get(at..s.start), m.text.get(s.clone())) else { continue };
+                    text.push_str(&format!("{}{}{}{}", before, MATCH_START, matched, MATCH_END));
+                    at = s.end;
+                }
+                text.push_str(&m.text[at..]);
+                return vec![format!("{}:{}:{}", m.path.display(), m.line, text)];
+            },
+        }
+    }
+}
+
+/// Writes a whole result set.
+pub fn write(set: &ResultSet, results: &[&Match], format: Format, out: &mut impl Write) -> io::Result<()> {
+    if let Some(h) = format.header(&set.directory) {
+        writeln!(out, "{}", h)?;
+    }
+    for m in results {
+        for l in format.lines(&set.directory, m) {
+            writeln!(out, "{}", l)?;
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn formats() {
+        let mut m = Match::new(PathBuf::from("src/a.rs"), 3, "é x = x;".to_string());
+        m.spans = vec![3..4, 7..8];
+        let dir = Path::new("/p");
+        assert_eq!(Format::Vim.lines(dir, &m), ["/p/src/a.rs:3:4:é x = x;", "/p/src/a.rs:3:8:é x = x;"]);
+        assert_eq!(Format::Vscode.lines(dir, &m)[1], "/p/src/a.rs:3:7:3:8: info: é x = x;");
+        assert_eq!(Format::Emacs.lines(dir, &m), ["src/a.rs:3:é \x1b[01;31m\x1b[Kx\x1b[m\x1b[K = \x1b[01;31m\x1b[Kx\x1b[m\x1b[K;"]);
+        assert_eq!(Format::Emacs.header(dir).unwrap(), "-*- mode: grep; default-directory: \"/p/\" -*-");
+        assert_eq!(Format::Vim.lines(dir, &Match::new(PathBuf::from("b"), 1, "y".to_string())), ["/p/b:1:1:y"]);
+        assert_eq!("code".parse(), Ok(Format::Vscode));
+        let mut m = Match::new(PathBuf::from("b"), 1, "y".to_string());
+        m.column = Some(0);
+        assert_eq!(Format::Vim.lines(dir, &m), ["/p/b:1:1:y"]);
+        m.path = PathBuf::from(STDIN_PATH);
+        assert!(Format::Vim.lines(dir, &m).is_empty());
+    }
+}
@@ -0,0 +1,8 @@
+#![allow(clippy::needless_return)]
+
+pub mod cache;
+pub mod command;
+pub mod export;
+pub mod matches;
+pub mod pipeline;
+pub mod search;
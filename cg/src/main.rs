@@ -1,84 +1,101 @@
-use std::collections::LinkedList;
-use std::process::{Command,Output,Stdio,Child};
-use std::{io, fs};
+#![allow(clippy::needless_return)]
+use std::io::{self, IsTerminal, Write};
 use clap::Parser;
 
-mod command;
+use cg::cache;
+use cg::command;
+use cg::command::shell;
+use cg::command::tools::Exit;
+use cg::pipeline::{Cancel, Reason};
+use cg::search;
 
- 
-
-///Creates a command from a command string.
-/*fn build(command: Vec<&str>) -> Command {
-    let mut output = Command::new(command.get(0).expect("No command attached!"));
-
-    for i in 1..command.len() {
-        output.arg(command[i]);
+/// Runs the search, and returns how it went.
+fn run() -> Result<Exit, Box<dyn std::error::Error>> {
+    let mut args = command::Args::parse();
+    if piped_stdin() {
+        args.default_to_stdin();
     }
-    return output;
-}*/
-fn build(command: LinkedList<&str>) -> Command {
-    let mut itr = command.iter();
-    let mut output = Command::new(itr.next().expect("No command attached!"));
-
-    for i in itr {
-        output.arg(i);
+    let backends = command::config::load()?;
+    let settings = command::config::settings()?;
+    let backend = command::tools::select(&backends, &settings.order, args.backend())?;
+    if let Some(shell) = args.print_command() {
+        let lines = search::command_lines(backend, args)?.ok_or("the native backend runs inside cg, there is no command line to print")?;
+        for l in lines {
+            println!("{}", shell::join(&l, shell));
+        }
+        return Ok(Exit::Matched);
+    }
+    let cancel = Cancel::new();
+    let handler = cancel.clone();
+    ctrlc::set_handler(move || {
+        //A second Ctrl-C does not wait for the backend to die.
+        if handler.reason().is_some() {
+            std::process::exit(Exit::Interrupted.code());
+        }
+        handler.cancel(Reason::Interrupted);
+    })?;
+    let columns = args.column();
+    let colors = args.color().enabled(io::stdout().is_terminal());
+    let export = args.export();
+    let directory = std::env::current_dir()?;
+    let patterns = args.all_patterns();
+    let mut out = io::stdout().lock();
+    if let Some(header) = export.and_then(|e| e.header(&directory)) {
+        writeln!(out, "{}", header)?;
     }
-    return output;
+    let mut listed = Vec::new();
+    let outcome = search::run(backend, args, &mut |m| {
+        match export {
+            Some(e) => e.lines(&directory, &m).iter().try_for_each(|l| writeln!(out, "{}", l))?,
+            None => writeln!(out, "{}", m.list(listed.len() + 1, columns, colors))?,
+        }
+        out.flush()?;
+        listed.push(m);
+        return Ok(());
+    }, &cancel);
+    //Even an empty result set replaces the last one, so that vg never opens results of an older search. Results listed
+    //before the search failed are kept too, as they were numbered already.
+    if let Err(e) = save(patterns, listed) {
+        eprintln!("cg: could not save the results for vg: {}", e);
+    }
+    let outcome = outcome?;
+    if let Some(reason) = outcome.cancelled {
+        for s in outcome.reports.iter().flat_map(|r| r.stages.iter()).filter(|s| s.status.code().is_none()) {
+            eprintln!("cg: {}: {}", reason, s);
+        }
+        eprintln!("cg: {}, {} results found until then", reason, outcome.results);
+    }
+    return Ok(outcome.exit);
 }
 
-///Call the first command in a call chain
-fn begin(first: LinkedList<&str>) -> Child {
-    return build(first).stdout(Stdio::piped()).spawn().expect("Failed command");
-}
-/// Links the first command's ouput to the second's input, then starts the second command.
-fn link(first: Child, second: LinkedList<&str>) -> Child {
-    //first.stdout(Stdio::piped());
-    return build(second).stdin(first.stdout.unwrap()).stdout(Stdio::piped()).spawn().expect("Failed command");
-}
-///Finishes a call stack
-fn finish(last: Child) -> Result<Output, io::Error> {
-    return last.wait_with_output();
+/// Keeps the results, in the order they were listed, for vg.
+fn save(patterns: Vec<String>, results: Vec<cg::matches::Match>) -> Result<(), Box<dyn std::error::Error>> {
+    let set = cache::ResultSet::new(patterns, results)?;
+    cache::write(&cache::path()?, &set)?;
+    return Ok(());
 }
 
-//NOTE: Search for command in PATH, try to find rust crate
-// Format stdout facile a lire avec grep
-
-use regex::Regex;
-
-use crate::command::framework::Convertible;
-
-fn rc<'a>(cap: &Option<regex::Match<'a>>) -> &'a str {
-    match cap {
-        Some(x) => {
-            if x.start() == x.end() {
-                return "None"
-            } else {
-                return x.as_str()
-            }
-        },
-        None => "",
+/// Whether something is piped or redirected into cg. A terminal, or /dev/null as scripts and editors often leave it,
+/// do not count: searching them would wait forever, or find nothing.
+fn piped_stdin() -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        return std::fs::metadata("/dev/stdin").is_ok_and(|m| m.file_type().is_fifo() || m.file_type().is_file());
+    }
+    #[cfg(not(unix))]
+    {
+        use std::io::IsTerminal;
+        return !io::stdin().is_terminal();
     }
 }
 
 fn main() {
-    let r = Regex::new(r"((?P<n1>#\d{1,3})|(?P<n2>-\pL)|(?P<n3>--\pL+))((?P<d1>!)|(?P<d2><[\pL-]*\[\pL*\]>)|(?P<d3>))(?P<s>\{\pL+\})((?P<t1>\[\pL+\])|(?P<t2>))").unwrap();
-    let text = "#1<-[str]>{path}[path] -i{casei}  #0!{pattern}[str] --estrogen{estr}[int]";
-    for cap in r.captures_iter(text) {
-        println!("{}{}{} {}{}{} {} {}{}", 
-            rc(&cap.name("n1")), 
-            rc(&cap.name("n2")), 
-            rc(&cap.name("n3")), 
-            rc(&cap.name("d1")), 
-            rc(&cap.name("d2")),
-            rc(&cap.name("d3")),
-            rc(&cap.name("s") ),
-            rc(&cap.name("t1")),
-            rc(&cap.name("t2")));
+    match run() {
+        Ok(exit) => std::process::exit(exit.code()),
+        Err(e) => {
+            eprintln!("cg: {}", e);
+            std::process::exit(search::exit_for(e.as_ref()).code());
+        },
     }
-    let args = command::Args::parse();
-    println!("{:?}", args);
-    let q = command::tools::GREP.clone().populate(args);
-    println!("{:?}", q);
-    let p = command::tools::Grepper::generate(q);
-    println!("{:?}", p);
-}
\ No newline at end of file
+}
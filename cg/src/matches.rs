@@ -0,0 +1,367 @@
+//! Search results, as parsed from what backends print.
+use std::env;
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+
+/// The path results read from standard input go by, whatever the backend called it.
+pub const STDIN_PATH: &str = "<stdin>";
+
+/// A line that matched.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Match {
+    pub path: PathBuf,
+    /// Starting from 1.
+    pub line: usize,
+    /// Of the first match in the line, in bytes, starting from 1. None if the backend did not say, and it could not be found.
+    pub column: Option<usize>,
+    /// Of the first match in the line, from the start of the file. None if the backend did not say.
+    pub offset: Option<usize>,
+    /// The whole line, without its line break.
+    pub text: String,
+    /// Where in `text` each match is, in bytes. Lines that were not valid UTF-8 may have them off character boundaries.
+    pub spans: Vec<Range<usize>>,
+    /// Lines shown before and after the match, when the backend gave some. Not cached.
+    #[serde(skip)]
+    pub before: Vec<String>,
+    #[serde(skip)]
+    pub after: Vec<String>,
+}
+
+impl Match {
+    /// A match with only what every output format tells.
+    pub fn new(path: PathBuf, line: usize, text: String) -> Self {
+        return Match { path, line, column: None, offset: None, text, spans: Vec::new(), before: Vec::new(), after: Vec::new() };
+    }
+    /// The text of the first match in the line, if known.
+    pub fn matched(&self) -> Option<&str> {
+        return self.spans.first().and_then(|s| self.text.get(s.clone()));
+    }
+    /// Finds the spans, and the column, when the backend did not give them.
+    pub fn highlight(&mut self, re: &Regex) {
+        if self.spans.is_empty() {
+            self.spans = re.find_iter(self.text.as_bytes()).map(|m| m.range()).filter(|r| !r.is_empty()).collect();
+        }
+        if self.column.is_none() {
+            self.column = self.spans.first().map(|s| s.start + 1);
+        }
+    }
+    /// The match as backends print it: path:line:text, or path:line:column:text.
+    pub fn render(&self, columns: bool) -> String {
+        match self.column {
+            Some(c) if columns => format!("{}:{}:{}:{}", self.path.display(), self.line, c, self.text),
+            _ => format!("{}:{}:{}", self.path.display(), self.line, self.text),
+        }
+    }
+    /// The match as cg and vg list it: its number n, then what `render` gives. Colours, when wanted, set the number,
+    /// the path and the line apart, and show where it matched.
+    pub fn list(&self, n: usize, columns: bool, colors: bool) -> String {
+        if !colors {
+            return format!("{} {}", n, self.render(columns));
+        }
+        let mut r = format!("{}{}{} {}{}{}:{}{}{}:", INDEX, n, RESET, PATH, self.path.display(), RESET, LINE, self.line, RESET);
+        if let Some(c) = self.column.filter(|_| columns) {
+            r.push_str(&format!("{}{}{}:", LINE, c, RESET));
+        }
+        r.push_str(&self.colored_text());
+        return r;
+    }
+    /// The text, with its spans coloured. Spans off character boundaries or overlapping earlier ones are left plain.
+    pub fn colored_text(&self) -> String {
+        let mut r = String::new();
+        let mut at = 0;
+        for s in &self.spans {
+            let (Some(before), Some(matched)) = (self.text.get(at..s.start), self.text.get(s.clone())) else { continue };
+            r.push_str(before);
+            r.push_str(&format!("{}{}{}", MATCH, matched, RESET));
+            at = s.end;
+        }
+        r.push_str(&self.text[at..]);
+        return r;
+    }
+}
+
+/// The colours of listings: those of rg, with the number in yellow.
+pub const INDEX: &str = "\x1b[33m";
+pub const PATH: &str = "\x1b[35m";
+pub const LINE: &str = "\x1b[32m";
+pub const MATCH: &str = "\x1b[1;31m";
+pub const RESET: &str = "\x1b[0m";
+
+/// When to colour listings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Color {
+    /// When writing to a terminal, unless NO_COLOR is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+impl Color {
+    /// Whether to colour, given whether the output is a terminal.
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            Color::Auto => terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
+}
+impl FromStr for Color {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Color::Auto),
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            _ => Err(format!("unknown color choice `{}`, expected auto, always or never", s)),
+        }
+    }
+}
+impl Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Auto => write!(f, "auto"),
+            Color::Always => write!(f, "always"),
+            Color::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// How a backend prints its results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// path:line:text, or path:line:column:text. Paths holding :<number>: confuse it.
+    #[default]
+    Colon,
+    /// path\0line:text, or path\0line:column:text, i.e. grep -Z.
+    PathNull,
+    /// path\0line\0text, or path\0line\0column\0text, i.e. git grep --null.
+    Null,
+    /// rg --json
+    Json,
+}
+
+/// Reads results line by line. Some formats need to see what comes next, so that matches may come out a line late.
+#[derive(Debug)]
+pub struct Parser {
+    format: Format,
+    /// Whether results hold a column.
+    columns: bool,
+    /// The last match read, waiting for its context.
+    pending: Option<Match>,
+    /// Context read before any match.
+    before: Vec<String>,
+}
+
+impl Parser {
+    pub fn new(format: Format, columns: bool) -> Self {
+        return Parser { format, columns, pending: None, before: Vec::new() };
+    }
+    /// Reads a line of output, and returns the matches it completes. None if the line could not be understood.
+    pub fn feed(&mut self, line: &[u8]) -> Option<Vec<Match>> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        match self.format {
+            Format::Colon => return colon(line, self.columns).map(|m| vec![m]),
+            Format::PathNull => return path_null(line, self.columns).map(|m| vec![m]),
+            Format::Null => return null(line, self.columns).map(|m| vec![m]),
+            Format::Json => return self.json(line),
+        }
+    }
+    /// Whatever is still waiting, once the output is over.
+    pub fn finish(&mut self) -> Option<Match> {
+        return self.pending.take();
+    }
+    fn json(&mut self, line: &[u8]) -> Option<Vec<Match>> {
+        let message: JsonMessage = serde_json::from_slice(line).ok()?;
+        match message {
+            JsonMessage::Match(m) => {
+                let mut next = m.into_matches();
+                let last = next.pop()?;
+                let mut r: Vec<Match> = self.pending.replace(last).into_iter().collect();
+                //Context before goes to the first line, context after to the last, which waits for it.
+                let first = next.first_mut().or(self.pending.as_mut())?;
+                first.before = std::mem::take(&mut self.before);
+                r.extend(next);
+                return Some(r);
+            },
+            JsonMessage::Context(c) => {
+                let text = c.lines.text?.trim_end_matches(['\n', '\r']).to_string();
+                //Context closer to the next match than to the last one goes to the next one: rg prints -A lines, then -B lines.
+                match self.pending.as_mut() {
+                    Some(p) if p.path.to_str() == c.path.text.as_deref() && c.line_number == Some(p.line + 1 + p.after.len()) => p.after.push(text),
+                    _ => self.before.push(text),
+                }
+                return Some(Vec::new());
+            },
+            JsonMessage::Begin {} | JsonMessage::End {} | JsonMessage::Summary {} => {
+                self.before.clear();
+                return Some(self.pending.take().into_iter().collect());
+            },
+        }
+    }
+}
+
+/// Splits a number followed by a separator off the front. None if there is no number.
+fn number(s: &[u8], separator: u8) -> Option<(usize, &[u8])> {
+    let end = s.iter().position(|b| !b.is_ascii_digit())?;
+    if end == 0 || s[end] != separator {
+        return None;
+    }
+    let n = std::str::from_utf8(&s[..end]).ok()?.parse().ok()?;
+    return Some((n, &s[end + 1..]));
+}
+
+/// line:text or line:column:text, or the same with another separator.
+fn fields(path: &[u8], rest: &[u8], separator: u8, columns: bool) -> Option<Match> {
+    let (line, rest) = number(rest, separator)?;
+    let (column, rest) = if columns {
+        let (c, rest) = number(rest, separator)?;
+        (Some(c), rest)
+    } else {
+        (None, rest)
+    };
+    let mut m = Match::new(PathBuf::from(String::from_utf8_lossy(path).into_owned()), line, String::from_utf8_lossy(rest).into_owned());
+    m.column = column;
+    return Some(m);
+}
+
+fn colon(line: &[u8], columns: bool) -> Option<Match> {
+    //The path ends at the first :<number>: there is.
+    let mut from = 0;
+    while let Some(i) = line[from..].iter().position(|b| *b == b':').map(|i| i + from) {
+        if let Some(m) = fields(&line[..i], &line[i + 1..], b':', columns) {
+            return Some(m);
+        }
+        from = i + 1;
+    }
+    return None;
+}
+
+fn path_null(line: &[u8], columns: bool) -> Option<Match> {
+    let i = line.iter().position(|b| *b == 0)?;
+    return fields(&line[..i], &line[i + 1..], b':', columns);
+}
+
+fn null(line: &[u8], columns: bool) -> Option<Match> {
+    let i = line.iter().position(|b| *b == 0)?;
+    return fields(&line[..i], &line[i + 1..], 0, columns);
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+enum JsonMessage {
+    Begin {},
+    End {},
+    Summary {},
+    Match(JsonLine),
+    Context(JsonLine),
+}
+
+/// rg gives paths and lines that are not UTF-8 as base64 bytes, which are skipped.
+#[derive(Deserialize)]
+struct JsonText {
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JsonSubmatch {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Deserialize)]
+struct JsonLine {
+    path: JsonText,
+    lines: JsonText,
+    line_number: Option<usize>,
+    absolute_offset: Option<usize>,
+    #[serde(default)]
+    submatches: Vec<JsonSubmatch>,
+}
+
+impl JsonLine {
+    /// A result for each line holding a match, with the spans cut to it. Only multiline searches give several.
+    fn into_matches(self) -> Vec<Match> {
+        let (Some(text), Some(path), Some(number)) = (self.lines.text, self.path.text, self.line_number) else { return Vec::new() };
+        let mut r = Vec::new();
+        let mut start = 0;
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let content = line.trim_end_matches(['\n', '\r']);
+            let (end, next) = (start + content.len(), start + line.len());
+            //Matches ending right after a line break do not reach the next line, empty ones stay on theirs.
+            let spans: Vec<Range<usize>> = self.submatches.iter()
+                .filter(|s| s.start < next && s.end.max(s.start + 1) > start)
+                .map(|s| s.start.clamp(start, end) - start..s.end.clamp(start, end) - start)
+                .collect();
+            //Without submatches, the first line still is a result, without spans.
+            if !spans.is_empty() || (self.submatches.is_empty() && i == 0) {
+                let mut m = Match::new(PathBuf::from(&path), number + i, content.to_string());
+                m.column = spans.first().map(|s| s.start + 1);
+                m.offset = self.absolute_offset.zip(spans.first()).map(|(o, s)| o + start + s.start);
+                m.spans = spans;
+                r.push(m);
+            }
+            start = next;
+        }
+        return r;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(format: Format, columns: bool, output: &[u8]) -> Vec<Match> {
+        let mut p = Parser::new(format, columns);
+        let mut r: Vec<Match> = output.split_inclusive(|b| *b == b'\n').flat_map(|l| p.feed(l).unwrap()).collect();
+        r.extend(p.finish());
+        return r;
+    }
+
+    #[test]
+    fn formats() {
+        let expected = |column| Match { column, ..Match::new(PathBuf::from("a:b.rs"), 12, "x: 1".to_string()) };
+        assert_eq!(parse(Format::Colon, false, b"a:b.rs:12:x: 1\n"), [expected(None)]);
+        assert_eq!(parse(Format::Colon, true, b"a:b.rs:12:3:x: 1\n"), [expected(Some(3))]);
+        assert_eq!(parse(Format::PathNull, true, b"a:b.rs\x0012:3:x: 1\n"), [expected(Some(3))]);
+        assert_eq!(parse(Format::Null, false, b"a:b.rs\x0012\x00x: 1\n"), [expected(None)]);
+        assert!(Parser::new(Format::Colon, false).feed(b"no match here").is_none());
+
+        let json = br#"{"type":"begin","data":{"path":{"text":"f"}}}
+{"type":"context","data":{"path":{"text":"f"},"lines":{"text":"a\n"},"line_number":1,"absolute_offset":0,"submatches":[]}}
+{"type":"match","data":{"path":{"text":"f"},"lines":{"text":"bxbx\n"},"line_number":2,"absolute_offset":2,"submatches":[{"match":{"text":"x"},"start":1,"end":2},{"match":{"text":"x"},"start":3,"end":4}]}}
+{"type":"context","data":{"path":{"text":"f"},"lines":{"text":"c\n"},"line_number":3,"absolute_offset":7,"submatches":[]}}
+{"type":"end","data":{"path":{"text":"f"},"binary_offset":null,"stats":{}}}
+"#;
+        let m = parse(Format::Json, false, json);
+        assert_eq!(m.len(), 1);
+        assert_eq!((m[0].line, m[0].column, m[0].offset, m[0].matched()), (2, Some(2), Some(3), Some("x")));
+        assert_eq!((m[0].spans.clone(), m[0].before.clone(), m[0].after.clone()), (vec![1..2, 3..4], vec!["a".to_string()], vec!["c".to_string()]));
+
+        //A multiline match gives a result for each of its lines.
+        let json = br#"{"type":"context","data":{"path":{"text":"f"},"lines":{"text":"a\n"},"line_number":1,"absolute_offset":0,"submatches":[]}}
+{"type":"match","data":{"path":{"text":"f"},"lines":{"text":"b {}\nlet x\n"},"line_number":2,"absolute_offset":2,"submatches":[{"match":{"text":"{}\nlet"},"start":2,"end":8}]}}
+{"type":"context","data":{"path":{"text":"f"},"lines":{"text":"c\n"},"line_number":4,"absolute_offset":12,"submatches":[]}}
+"#;
+        let m = parse(Format::Json, false, json);
+        let lines: Vec<_> = m.iter().map(|m| (m.line, m.text.as_str(), m.spans.first().cloned(), m.offset, m.before.len(), m.after.len())).collect();
+        assert_eq!(lines, [(2, "b {}", Some(2..4), Some(4), 1, 0), (3, "let x", Some(0..3), Some(7), 0, 1)]);
+    }
+
+    #[test]
+    fn highlighting() {
+        let mut m = Match::new(PathBuf::from("f"), 1, "let x = xy;".to_string());
+        m.highlight(&Regex::new("x+").unwrap());
+        assert_eq!((m.spans.clone(), m.column), (vec![4..5, 8..9], Some(5)));
+
+        assert_eq!(m.list(7, true, false), "7 f:1:5:let x = xy;");
+        assert_eq!(m.list(7, false, true), "\x1b[33m7\x1b[0m \x1b[35mf\x1b[0m:\x1b[32m1\x1b[0m:let \x1b[1;31mx\x1b[0m = \x1b[1;31mx\x1b[0my;");
+        assert!(!Color::Never.enabled(true) && Color::Always.enabled(false) && !Color::Auto.enabled(false));
+    }
+}
@@ -0,0 +1,487 @@
+//! Processes chained by their standard streams, as a shell would with |, i.e. `git ls-files -z | xargs -0 grep -n`.
+//! Every pipeline runs in a process group of its own, so that stopping it stops whatever its stages started too.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Where a standard stream of a stage goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Redirect {
+    /// Shared with cg.
+    Inherit,
+    /// /dev/null
+    Null,
+    /// Linked to the neighbouring stage. At either end of the pipeline, handed to the caller, see `Running`.
+    /// A piped stderr is collected into the stage's `Status`.
+    Pipe,
+    /// For stderr only: collected into the stage's `Status`, and passed on to cg's stderr as it comes.
+    Tee,
+}
+
+/// The environment of a stage: cg's own, minus what is removed or cleared, plus what is set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Environment {
+    /// Start from nothing rather than cg's environment.
+    pub clear: bool,
+    /// Variables to set (Some) or remove (None).
+    pub vars: BTreeMap<String, Option<String>>,
+}
+
+/// As shown to users: what changes from cg's environment.
+impl Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        if self.clear {
+            parts.push("cleared".to_string());
+        }
+        for (k, v) in &self.vars {
+            match v {
+                Some(v) => parts.push(format!("{}={}", k, v)),
+                None => parts.push(format!("-{}", k)),
+            }
+        }
+        if parts.is_empty() {
+            return write!(f, "inherited");
+        }
+        return write!(f, "{}", parts.join(", "));
+    }
+}
+
+/// A single process of a pipeline.
+#[derive(Clone, Debug)]
+pub struct Stage {
+    program: String,
+    args: Vec<String>,
+    stdin: Option<Redirect>,
+    stdout: Option<Redirect>,
+    stderr: Option<Redirect>,
+    env: Environment,
+}
+
+impl Stage {
+    /// A stage running a program. Unless told otherwise, stages are piped to each other, the ends inherit from cg,
+    /// and so does stderr.
+    pub fn new(program: &str) -> Self {
+        return Stage { program: program.to_string(), args: Vec::new(), stdin: None, stdout: None, stderr: None, env: Environment::default() };
+    }
+    /// A stage running a whole command line, program first. None if the line is empty.
+    pub fn command_line(line: &[String]) -> Option<Self> {
+        let (program, args) = line.split_first()?;
+        return Some(Stage::new(program).args(args));
+    }
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        return self;
+    }
+    pub fn args<S: AsRef<str>>(mut self, args: &[S]) -> Self {
+        self.args.extend(args.iter().map(|a| a.as_ref().to_string()));
+        return self;
+    }
+    pub fn stdin(mut self, r: Redirect) -> Self {
+        self.stdin = Some(r);
+        return self;
+    }
+    pub fn stdout(mut self, r: Redirect) -> Self {
+        self.stdout = Some(r);
+        return self;
+    }
+    pub fn stderr(mut self, r: Redirect) -> Self {
+        self.stderr = Some(r);
+        return self;
+    }
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.vars.insert(key.to_string(), Some(value.to_string()));
+        return self;
+    }
+    pub fn env_remove(mut self, key: &str) -> Self {
+        self.env.vars.insert(key.to_string(), None);
+        return self;
+    }
+    pub fn env_clear(mut self) -> Self {
+        self.env.clear = true;
+        return self;
+    }
+    /// Replaces the whole environment description.
+    pub fn environment(mut self, env: Environment) -> Self {
+        self.env = env;
+        return self;
+    }
+    /// What the stage's environment changes from cg's.
+    pub fn effective_env(&self) -> &Environment {
+        return &self.env;
+    }
+    /// What the stage goes by in reports: its command line.
+    pub fn label(&self) -> String {
+        return std::iter::once(&self.program).chain(self.args.iter()).cloned().collect::<Vec<_>>().join(" ");
+    }
+    fn command(&self) -> Command {
+        let mut c = Command::new(&self.program);
+        c.args(&self.args);
+        if self.env.clear {
+            c.env_clear();
+        }
+        for (k, v) in &self.env.vars {
+            match v {
+                Some(v) => c.env(k, v),
+                None => c.env_remove(k),
+            };
+        }
+        return c;
+    }
+}
+
+fn stdio(r: Redirect) -> Stdio {
+    match r {
+        Redirect::Inherit => Stdio::inherit(),
+        Redirect::Null => Stdio::null(),
+        Redirect::Pipe | Redirect::Tee => Stdio::piped(),
+    }
+}
+
+/// Why a pipeline could not be started.
+#[derive(Debug)]
+pub enum PipelineError {
+    /// There is no stage to run.
+    Empty,
+    /// A stage pipes into a neighbour that does not read from it, or the other way around.
+    Unlinked { stage: String },
+    /// A stage could not be started. The stages before it were killed.
+    Spawn { stage: String, error: io::Error },
+    /// Waiting on a stage failed.
+    Wait { stage: String, error: io::Error },
+}
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Empty => write!(f, "nothing to run"),
+            PipelineError::Unlinked { stage } => write!(f, "`{}` is not piped to its neighbour", stage),
+            PipelineError::Spawn { stage, error } => write!(f, "cannot run `{}`: {}", stage, error),
+            PipelineError::Wait { stage, error } => write!(f, "lost track of `{}`: {}", stage, error),
+        }
+    }
+}
+impl std::error::Error for PipelineError {}
+
+/// Why pipelines were stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// Ctrl-C
+    Interrupted,
+    /// The search took longer than allowed.
+    TimedOut(Duration),
+}
+impl Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Interrupted => write!(f, "interrupted"),
+            Reason::TimedOut(d) => write!(f, "timed out after {}s", d.as_secs_f64()),
+        }
+    }
+}
+
+/// Stops pipelines from elsewhere, i.e. a Ctrl-C handler or a timer. Pipelines given a handle register their process
+/// group while running; cancelling kills the groups registered, and those registered later right away.
+#[derive(Clone, Debug, Default)]
+pub struct Cancel {
+    state: Arc<Mutex<CancelState>>,
+}
+#[derive(Debug, Default)]
+struct CancelState {
+    reason: Option<Reason>,
+    groups: BTreeSet<u32>,
+}
+
+impl Cancel {
+    pub fn new() -> Self {
+        return Cancel::default();
+    }
+    fn lock(&self) -> MutexGuard<'_, CancelState> {
+        return self.state.lock().unwrap_or_else(|e| e.into_inner());
+    }
+    /// Kills every registered pipeline. Only the first reason is kept.
+    pub fn cancel(&self, reason: Reason) {
+        let mut state = self.lock();
+        state.reason.get_or_insert(reason);
+        for g in &state.groups {
+            kill_group(*g);
+        }
+    }
+    /// Why pipelines were stopped, if they were.
+    pub fn reason(&self) -> Option<Reason> {
+        return self.lock().reason;
+    }
+    fn register(&self, group: u32) {
+        let mut state = self.lock();
+        if state.reason.is_some() {
+            kill_group(group);
+        }
+        state.groups.insert(group);
+    }
+    fn unregister(&self, group: u32) {
+        self.lock().groups.remove(&group);
+    }
+}
+
+#[cfg(unix)]
+fn kill_group(group: u32) {
+    //SAFETY: killpg has no memory effects. The group cannot have been reused: it is unregistered before its leader is reaped.
+    unsafe {
+        libc::killpg(group as libc::pid_t, libc::SIGKILL);
+    }
+}
+#[cfg(not(unix))]
+fn kill_group(_group: u32) {
+    //No process groups here. Stages are only killed through Running::kill.
+}
+
+/// Stages to run together, each one reading what the one before writes.
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    cancel: Option<Cancel>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        return Pipeline::default();
+    }
+    /// Appends a stage.
+    pub fn stage(mut self, s: Stage) -> Self {
+        self.stages.push(s);
+        return self;
+    }
+    /// Lets the pipeline be stopped through a `Cancel` handle.
+    pub fn cancel(mut self, cancel: &Cancel) -> Self {
+        self.cancel = Some(cancel.clone());
+        return self;
+    }
+    /// Each stage's redirections, with the defaults filled in.
+    fn redirections(&self) -> Result<Vec<(Redirect, Redirect, Redirect)>, PipelineError> {
+        let last = self.stages.len() - 1;
+        let r: Vec<_> = self.stages.iter().enumerate().map(|(i, s)| (
+            s.stdin.unwrap_or(if i == 0 { Redirect::Inherit } else { Redirect::Pipe }),
+            s.stdout.unwrap_or(if i == last { Redirect::Inherit } else { Redirect::Pipe }),
+            s.stderr.unwrap_or(Redirect::Inherit),
+        )).collect();
+        for i in 0..last {
+            if (r[i].1 == Redirect::Pipe) != (r[i + 1].0 == Redirect::Pipe) {
+                return Err(PipelineError::Unlinked { stage: self.stages[i].label() });
+            }
+        }
+        return Ok(r);
+    }
+    /// Starts every stage. Should one fail to start, those already started are killed.
+    pub fn spawn(self) -> Result<Running, PipelineError> {
+        if self.stages.is_empty() {
+            return Err(PipelineError::Empty);
+        }
+        let redirections = self.redirections()?;
+        let mut running = Running { stages: Vec::new(), stdin: None, stdout: None, group: None, cancel: self.cancel };
+        let mut previous: Option<ChildStdout> = None;
+        for (s, (i, o, e)) in self.stages.into_iter().zip(redirections) {
+            let mut c = s.command();
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::CommandExt;
+                c.process_group(running.group.unwrap_or(0) as i32);
+            }
+            c.stdin(match (i, previous.take()) {
+                (Redirect::Pipe, Some(out)) => Stdio::from(out),
+                (i, _) => stdio(i),
+            });
+            c.stdout(stdio(o)).stderr(stdio(e));
+            let mut child = match c.spawn() {
+                Ok(c) => c,
+                Err(error) => {
+                    running.kill();
+                    return Err(PipelineError::Spawn { stage: s.label(), error });
+                },
+            };
+            if running.stages.is_empty() {
+                running.stdin = child.stdin.take();
+                running.group = Some(child.id());
+                if let Some(c) = &running.cancel {
+                    c.register(child.id());
+                }
+            }
+            previous = child.stdout.take();
+            let tee = e == Redirect::Tee;
+            let stderr = child.stderr.take().map(|err| thread::spawn(move || collect(err, tee)));
+            running.stages.push((s.label(), child, stderr));
+        }
+        running.stdout = previous;
+        return Ok(running);
+    }
+    /// Runs every stage to completion.
+    pub fn run(self) -> Result<Report, PipelineError> {
+        return self.spawn()?.wait();
+    }
+}
+
+/// Reads a stderr to its end, passing it on if asked to.
+fn collect(mut from: impl Read, tee: bool) -> String {
+    let mut r = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        match from.read(&mut buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if tee {
+                    let _ = io::stderr().write_all(&buf[..n]);
+                }
+                r.extend_from_slice(&buf[..n]);
+            },
+        }
+    }
+    return String::from_utf8_lossy(&r).into_owned();
+}
+
+/// A started pipeline.
+#[derive(Debug)]
+pub struct Running {
+    stages: Vec<(String, Child, Option<JoinHandle<String>>)>,
+    /// The first stage's stdin, when piped.
+    pub stdin: Option<ChildStdin>,
+    /// The last stage's stdout, when piped.
+    pub stdout: Option<ChildStdout>,
+    /// The process group of every stage, named after the first one.
+    group: Option<u32>,
+    cancel: Option<Cancel>,
+}
+
+impl Running {
+    /// Kills every stage still running, and whatever they started.
+    pub fn kill(&mut self) {
+        if let Some(g) = self.group {
+            kill_group(g);
+        }
+        for (_, c, _) in self.stages.iter_mut() {
+            let _ = c.kill();
+            let _ = c.wait();
+        }
+        self.unregister();
+    }
+    fn unregister(&mut self) {
+        if let (Some(c), Some(g)) = (&self.cancel, self.group.take()) {
+            c.unregister(g);
+        }
+    }
+    /// Waits for every stage to finish. The pipes handed to the caller are closed first, so that no stage waits on them.
+    pub fn wait(mut self) -> Result<Report, PipelineError> {
+        drop(self.stdin.take());
+        drop(self.stdout.take());
+        let mut r = Vec::new();
+        let mut stages = std::mem::take(&mut self.stages).into_iter();
+        //The first stage leads the group: it has to be unregistered before it is reaped, lest its pid be reused.
+        if let Some((label, mut c, stderr)) = stages.next() {
+            let status = exited(&c);
+            self.unregister();
+            let status = status.and_then(|_| c.wait());
+            r.push(Status { stage: label.clone(), status: status.map_err(|error| PipelineError::Wait { stage: label, error })?, stderr: join(stderr) });
+        }
+        for (label, mut c, stderr) in stages {
+            let status = c.wait().map_err(|error| PipelineError::Wait { stage: label.clone(), error })?;
+            r.push(Status { stage: label, status, stderr: join(stderr) });
+        }
+        return Ok(Report { stages: r });
+    }
+}
+
+/// Blocks until a child exits, without reaping it.
+#[cfg(unix)]
+fn exited(c: &Child) -> io::Result<()> {
+    loop {
+        //SAFETY: info is a plain struct waitid fills in.
+        let r = unsafe {
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            libc::waitid(libc::P_PID, c.id() as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT)
+        };
+        if r == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+#[cfg(not(unix))]
+fn exited(_c: &Child) -> io::Result<()> {
+    return Ok(());
+}
+
+fn join(stderr: Option<JoinHandle<String>>) -> String {
+    return stderr.map(|h| h.join().unwrap_or_default()).unwrap_or_default();
+}
+
+/// How a stage ended.
+#[derive(Debug, Clone)]
+pub struct Status {
+    /// The stage, see `Stage::label`.
+    pub stage: String,
+    pub status: ExitStatus,
+    /// What the stage wrote to stderr, when piped.
+    pub stderr: String,
+}
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status.code() {
+            Some(c) => write!(f, "`{}` exited with status {}", self.stage, c)?,
+            None => write!(f, "`{}` was killed", self.stage)?,
+        }
+        match self.stderr.trim() {
+            "" => Ok(()),
+            e => write!(f, ": {}", e),
+        }
+    }
+}
+
+/// How every stage of a pipeline ended, in order.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub stages: Vec<Status>,
+}
+impl Report {
+    /// The first stage that did not succeed, if any.
+    pub fn failure(&self) -> Option<&Status> {
+        return self.stages.iter().find(|s| !s.status.success());
+    }
+    /// How the last stage ended, which is what a shell would report.
+    pub fn last(&self) -> Option<&Status> {
+        return self.stages.last();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages() {
+        let mut r = Pipeline::new()
+            .stage(Stage::new("printf").arg("a\\nb\\nab\\n"))
+            .stage(Stage::new("grep").arg("b").stdout(Redirect::Pipe))
+            .spawn().unwrap();
+        let mut out = String::new();
+        r.stdout.take().unwrap().read_to_string(&mut out).unwrap();
+        assert_eq!(out, "b\nab\n");
+        assert!(r.wait().unwrap().failure().is_none());
+
+        let report = Pipeline::new()
+            .stage(Stage::new("sh").args(&["-c", "echo oops >&2; exit 3"]).stderr(Redirect::Pipe))
+            .stage(Stage::new("cat").env_clear().env("A", "b"))
+            .run().unwrap();
+        assert_eq!(report.failure().unwrap().to_string(), "`sh -c echo oops >&2; exit 3` exited with status 3: oops");
+        assert!(report.last().unwrap().status.success());
+
+        let e = Pipeline::new().stage(Stage::new("true")).stage(Stage::new("cg-surely-not-installed")).run().unwrap_err();
+        assert!(e.to_string().starts_with("cannot run `cg-surely-not-installed`: "));
+        let e = Pipeline::new().stage(Stage::new("true").stdout(Redirect::Null)).stage(Stage::new("cat")).run().unwrap_err();
+        assert!(matches!(e, PipelineError::Unlinked { .. }));
+    }
+}
@@ -0,0 +1,369 @@
+//! Running a search: the chosen backend is started, and its results are passed on as they arrive.
+use std::fmt::{self, Display};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use regex::bytes::Regex;
+use std::thread;
+use crate::command::capabilities::{self, CapabilityError};
+use crate::command::framework;
+use crate::command::native;
+use crate::command::shell::{self, Shell};
+use crate::command::tools::{Exit, Grepper, SelectError};
+use crate::command::Args;
+use crate::matches::{Match, Parser, STDIN_PATH};
+use crate::pipeline::{Cancel, Pipeline, PipelineError, Reason, Redirect, Report, Stage};
+
+/// How many results each root may have waiting to be written. Roots waiting on those before them stop there, and so
+/// do their backends, rather than piling their results up in memory.
+const RESULTS_AHEAD: usize = 1024;
+
+/// Why a search could not be run.
+#[derive(Debug)]
+pub enum SearchError {
+    /// The backend cannot do what was asked.
+    Capability(CapabilityError),
+    /// The command line could not be generated.
+    Command(framework::Error),
+    /// The backend could not be started.
+    Pipeline(PipelineError),
+    /// Reading the results, or writing them out, failed.
+    Io(io::Error),
+}
+impl Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::Capability(e) => write!(f, "{}", e),
+            SearchError::Command(e) => write!(f, "{}", e),
+            SearchError::Pipeline(e) => write!(f, "{}", e),
+            SearchError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for SearchError {}
+impl From<CapabilityError> for SearchError {
+    fn from(e: CapabilityError) -> Self {
+        return SearchError::Capability(e);
+    }
+}
+impl From<framework::Error> for SearchError {
+    fn from(e: framework::Error) -> Self {
+        return SearchError::Command(e);
+    }
+}
+impl From<PipelineError> for SearchError {
+    fn from(e: PipelineError) -> Self {
+        return SearchError::Pipeline(e);
+    }
+}
+impl From<io::Error> for SearchError {
+    fn from(e: io::Error) -> Self {
+        return SearchError::Io(e);
+    }
+}
+
+/// How a search went.
+#[derive(Debug)]
+pub struct Outcome {
+    /// How many results were written.
+    pub results: usize,
+    /// What cg exits with, see `ExitCodes`.
+    pub exit: Exit,
+    /// How the backend's processes ended, one report per root. None for the native backend.
+    pub reports: Vec<Report>,
+    /// Why the search was cut short, if it was. Results already written are kept.
+    pub cancelled: Option<Reason>,
+}
+
+/// How the search of a single root went.
+struct Part {
+    results: usize,
+    exit: Exit,
+    /// One per backend run.
+    reports: Vec<Report>,
+}
+
+/// Runs a search, handing each result to `emit` as soon as the backend prints it. Results lacking spans or a column get
+/// them from the patterns. `cancel` stops the search, as does the timeout of `args`.
+///
+/// With several roots, each one gets its own backend process, all running at once. Their results are merged root by root,
+/// in the order the roots were given: those of the first root are written as they come, those of the others once the roots
+/// before them are done. Numbering the merged stream therefore always gives the same numbers.
+///
+/// A root that cannot be searched, i.e. one that does not exist, is reported and makes the search an error, but the
+/// others are still searched. Only when no root could be is its error returned.
+pub fn run(backend: &Grepper, args: Args, emit: &mut impl FnMut(Match) -> io::Result<()>, cancel: &Cancel) -> Result<Outcome, SearchError> {
+    return thread::scope(|scope| {
+        //The timer goes off unless the search is over first, which hangs up on it.
+        let (done, timer) = mpsc::channel::<()>();
+        if let Some(t) = args.timeout() {
+            scope.spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = timer.recv_timeout(t) {
+                    cancel.cancel(Reason::TimedOut(t));
+                }
+            });
+        }
+        let jobs: Vec<_> = args.split().into_iter().map(|a| {
+            let (tx, rx) = mpsc::sync_channel(RESULTS_AHEAD);
+            return (scope.spawn(move || search(backend, a, tx, cancel)), rx);
+        }).collect();
+
+        let mut writing = true;
+        let mut parts = Vec::new();
+        let mut failures = Vec::new();
+        for (job, rx) in jobs {
+            //Once whoever reads the results is gone, receivers are dropped unread, which stops the jobs sending to them.
+            if writing {
+                for m in rx.iter() {
+                    match emit(m) {
+                        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                            writing = false;
+                            break;
+                        },
+                        r => r?,
+                    }
+                }
+            }
+            //Hung up on before waiting, as a job may be stuck sending to it.
+            drop(rx);
+            match job.join().unwrap_or_else(|_| Err(SearchError::Io(io::Error::other("a search thread panicked")))) {
+                Ok(p) => parts.push(p),
+                Err(e) => failures.push(e),
+            }
+        }
+        drop(done);
+        if parts.is_empty() && !failures.is_empty() {
+            let first = failures.remove(0);
+            //Roots failing alike, i.e. on a feature the backend lacks, need saying once.
+            for e in failures.iter().filter(|e| e.to_string() != first.to_string()) {
+                eprintln!("cg: {}", e);
+            }
+            return Err(first);
+        }
+        for e in failures {
+            eprintln!("cg: {}", e);
+            parts.push(Part { results: 0, exit: Exit::Error, reports: Vec::new() });
+        }
+        return Ok(merge(parts, cancel.reason()));
+    });
+}
+
+/// Sums the searches of every root up. Errors win over matches, and matches over their absence.
+/// A cancelled search is an error, or an interruption.
+fn merge(parts: Vec<Part>, cancelled: Option<Reason>) -> Outcome {
+    let results = parts.iter().map(|p| p.results).sum();
+    let exit = match cancelled {
+        Some(Reason::Interrupted) => Exit::Interrupted,
+        Some(Reason::TimedOut(_)) => Exit::Error,
+        None => parts.iter().map(|p| p.exit).max_by_key(severity).unwrap_or(Exit::Unmatched),
+    };
+    return Outcome { results, exit, reports: parts.into_iter().flat_map(|p| p.reports).collect(), cancelled };
+}
+
+/// How much an exit weighs when summing searches up.
+fn severity(exit: &Exit) -> u8 {
+    match exit {
+        Exit::Unmatched => 0,
+        Exit::Matched => 1,
+        Exit::Error => 2,
+        Exit::Missing => 3,
+        Exit::Interrupted => 4,
+    }
+}
+
+/// Searches a single root, sending results as they come.
+fn search(backend: &Grepper, args: Args, to: SyncSender<Match>, cancel: &Cancel) -> Result<Part, SearchError> {
+    let verbose = args.verbose();
+    //Roots are searched apart, so standard input comes alone.
+    let label = args.reads_stdin().then_some(Path::new(STDIN_PATH));
+    let plan = capabilities::plan(backend, args)?;
+    if backend.is_native() {
+        if verbose {
+            eprintln!("cg: searching natively, within cg");
+        }
+        let mut sent = 0;
+        let mut send = |m| {
+            to.send(m).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            sent += 1;
+            return Ok(());
+        };
+        match native::search(&plan.args, &mut send, cancel) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => (),
+        }
+        let exit = if sent > 0 { Exit::Matched } else { Exit::Unmatched };
+        return Ok(Part { results: sent, exit, reports: Vec::new() });
+    }
+    let columns = plan.args.column();
+    let re = native::regex(&plan.args).ok();
+    //Only a backend reading standard input gets it, the others would be left waiting on a terminal.
+    let input = if plan.args.reads_stdin() { Redirect::Inherit } else { Redirect::Null };
+    let batches = plan.batches();
+    //Kept until the backend is done with it.
+    let saved = plan.stdin;
+    if verbose {
+        if let Some(saved) = &saved {
+            eprintln!("cg: standard input saved to {}", saved.path().display());
+        }
+    }
+    let mut part = Part { results: 0, exit: Exit::Unmatched, reports: Vec::new() };
+    for args in batches {
+        if cancel.reason().is_some() {
+            break;
+        }
+        let line = backend.command_line(args)?;
+        let stage = Stage::command_line(&line).ok_or(PipelineError::Empty)?.stdin(input).stdout(Redirect::Pipe).stderr(Redirect::Tee)
+            .environment(backend.environment.resolve());
+        if verbose {
+            eprintln!("cg: running {}", shell::join(&line, Shell::Sh));
+            eprintln!("cg: environment: {}", stage.effective_env());
+        }
+        let mut running = Pipeline::new().stage(stage).cancel(cancel).spawn()?;
+        let forwarded = match running.stdout.take() {
+            Some(stdout) => forward(BufReader::new(stdout), Parser::new(backend.output.format, columns), re.as_ref(), label, &to),
+            None => Ok((0, false)),
+        };
+        //Wait even if forwarding failed, so that no process is left behind.
+        let report = running.wait()?;
+        let (results, hung_up) = forwarded?;
+        let exit = match report.last() {
+            //The backend was cut off along with its output, which says nothing about how it went.
+            Some(_) if hung_up => Exit::Matched,
+            Some(s) => backend.exit.classify(s.status.code(), results, &s.stderr),
+            None => Exit::Error,
+        };
+        part.results += results;
+        part.exit = std::cmp::max_by_key(part.exit, exit, severity);
+        part.reports.push(report);
+        if hung_up {
+            break;
+        }
+    }
+    return Ok(part);
+}
+
+/// The command line `run` would start for each root, emulations included, see `capabilities::preview`. None for the native
+/// backend.
+pub fn command_lines(backend: &Grepper, args: Args) -> Result<Option<Vec<Vec<String>>>, SearchError> {
+    if backend.is_native() {
+        return Ok(None);
+    }
+    let mut r = Vec::new();
+    for a in args.split() {
+        for b in capabilities::preview(backend, a)?.batches() {
+            r.push(backend.command_line(b)?);
+        }
+    }
+    return Ok(Some(r));
+}
+
+/// What cg exits with when a search could not even start: `Exit::Missing` when the backend is not installed,
+/// `Exit::Error` otherwise.
+pub fn exit_for(e: &(dyn std::error::Error + 'static)) -> Exit {
+    if let Some(e) = e.downcast_ref::<SelectError>() {
+        return match e {
+            SelectError::Unknown { .. } => Exit::Error,
+            SelectError::NotInstalled { .. } | SelectError::NoneInstalled { .. } => Exit::Missing,
+        };
+    }
+    match e.downcast_ref::<SearchError>() {
+        Some(SearchError::Pipeline(PipelineError::Spawn { error, .. })) if error.kind() == io::ErrorKind::NotFound => Exit::Missing,
+        _ => Exit::Error,
+    }
+}
+
+/// Parses results, and sends them under `label` when given, rather than the path the backend printed. Returns how many
+/// there were, and whether nobody listened anymore, i.e. cg | head, which stops it early without error.
+/// Lines that cannot be parsed go to stderr.
+fn forward(mut from: impl BufRead, mut parser: Parser, re: Option<&Regex>, label: Option<&Path>, to: &SyncSender<Match>) -> io::Result<(usize, bool)> {
+    let mut n = 0;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let matches = match from.read_until(b'\n', &mut line)? {
+            0 => parser.finish().into_iter().collect(),
+            _ => match parser.feed(&line) {
+                Some(m) => m,
+                None => {
+                    io::stderr().write_all(&line)?;
+                    continue;
+                },
+            },
+        };
+        for mut m in matches {
+            if let Some(re) = re {
+                m.highlight(re);
+            }
+            if let Some(label) = label {
+                m.path = label.to_path_buf();
+            }
+            if to.send(m).is_err() {
+                return Ok((n, true));
+            }
+            n += 1;
+        }
+        if line.is_empty() {
+            return Ok((n, false));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matches::Format;
+
+    #[test]
+    fn forwarding() {
+        let (tx, rx) = mpsc::sync_channel(2);
+        let re = Regex::new("y").unwrap();
+        assert_eq!(forward(&b"a:1:x\nb:2:xy"[..], Parser::new(Format::Colon, false), Some(&re), None, &tx).unwrap(), (2, false));
+        let r: Vec<Match> = rx.try_iter().collect();
+        assert_eq!(r.iter().map(|m| m.render(true)).collect::<Vec<_>>(), ["a:1:x", "b:2:2:xy"]);
+        forward(&b"(standard input):3:x\n"[..], Parser::new(Format::Colon, false), None, Some(Path::new(STDIN_PATH)), &tx).unwrap();
+        assert_eq!(rx.try_recv().unwrap().render(false), "<stdin>:3:x");
+    }
+
+    #[test]
+    fn fan_out() {
+        use clap::Parser;
+        let dir = std::env::temp_dir().join(format!("cg-fan-out-{}", std::process::id()));
+        let roots: Vec<String> = ["b", "a"].iter().map(|r| {
+            std::fs::create_dir_all(dir.join(r)).unwrap();
+            std::fs::write(dir.join(r).join("f"), "x\nyx\n").unwrap();
+            return dir.join(r).to_string_lossy().into_owned();
+        }).collect();
+        let missing = dir.join("missing").to_string_lossy().into_owned();
+        let search = |roots: &[&str]| {
+            let args = Args::parse_from(["cg", "x"].iter().chain(roots));
+            let mut out = String::new();
+            let outcome = run(&native::definition(), args, &mut |m| {
+                out.push_str(&format!("{}\n", m.render(false)));
+                return Ok(());
+            }, &Cancel::new());
+            return outcome.map(|o| (o.results, o.exit, out));
+        };
+        let expected = format!("{b}/f:1:x\n{b}/f:2:yx\n{a}/f:1:x\n{a}/f:2:yx\n", b = roots[0], a = roots[1]);
+        assert_eq!(search(&[&roots[0], &roots[1]]).unwrap(), (4, Exit::Matched, expected.clone()));
+        //A missing root does not stop the others.
+        assert_eq!(search(&[&roots[0], &missing, &roots[1]]).unwrap(), (4, Exit::Error, expected));
+        assert!(search(&[&missing]).unwrap_err().to_string().starts_with(&missing));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn timeout() {
+        use clap::Parser;
+        let sleeper = Grepper { name: "sleep".to_string(), command: "sh".to_string(), implicit: vec!["-c".to_string(), "echo a:1:x; sleep 10 & sleep 10".to_string()],
+            entries: vec![], capabilities: Default::default(), unsupported: Default::default(), exit: Default::default(), environment: Default::default(), output: Default::default() };
+        let start = std::time::Instant::now();
+        let mut out = Vec::new();
+        let outcome = run(&sleeper, Args::parse_from(["cg", "x", "--timeout", "0.5"]), &mut |m| {
+            out.push(m.render(false));
+            return Ok(());
+        }, &Cancel::new()).unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert_eq!((out, outcome.exit), (vec!["a:1:x".to_string()], Exit::Error));
+        assert_eq!(outcome.cancelled, Some(Reason::TimedOut(std::time::Duration::from_millis(500))));
+    }
+}
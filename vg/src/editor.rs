@@ -0,0 +1,250 @@
+//! Opening results in the user's editor. Every editor has its own way of being told where to go, so each one gets an
+//! argument template, where {file}, {line}, {column} and {byte_column} stand for the result.
+//!
+//! Several results open in a single editor when it can take them all: through a quickfix file, or by repeating the
+//! template. Other editors are run once per result, one after the other.
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+use cg::cache::ResultSet;
+use cg::export;
+use cg::command::framework::{self, Entry, ErrorKind, Generate, SpecError, Transformable};
+use cg::command::shell;
+use cg::matches::{Match, STDIN_PATH};
+
+/// How an editor opens several results at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Many {
+    /// The template once per result, all in one command line.
+    Repeat,
+    /// A file listing the results as vim reads them, see `export::Format::Vim`, given after this flag.
+    Quickfix(&'static str),
+    /// It cannot: one editor is run after the other.
+    Sequential,
+}
+
+/// An editor vg knows.
+#[derive(Clone, Copy, Debug)]
+pub struct Known {
+    /// Its program names.
+    pub names: &'static [&'static str],
+    /// Its arguments for a single result. Columns count characters, except {byte_column}.
+    pub template: &'static [&'static str],
+    pub many: Many,
+}
+
+/// The editors vg knows.
+pub const EDITORS: &[Known] = &[
+    //cursor() takes bytes, as cg does.
+    Known { names: &["vim", "nvim", "gvim", "mvim"], template: &["+call cursor({line},{byte_column})", "{file}"], many: Many::Quickfix("-q") },
+    Known { names: &["vi"], template: &["+{line}", "{file}"], many: Many::Sequential },
+    Known { names: &["emacs", "emacsclient"], template: &["+{line}:{column}", "{file}"], many: Many::Repeat },
+    Known { names: &["code", "code-insiders", "codium"], template: &["-g", "{file}:{line}:{column}"], many: Many::Repeat },
+    Known { names: &["hx", "helix", "subl"], template: &["{file}:{line}:{column}"], many: Many::Repeat },
+    Known { names: &["nano"], template: &["+{line},{column}", "{file}"], many: Many::Repeat },
+];
+
+/// Editors vg does not know get the file alone, as there is no telling how they take a line.
+pub const UNKNOWN: Known = Known { names: &[], template: &["{file}"], many: Many::Sequential };
+
+/// A result that cannot be opened, or an editor that cannot be run.
+#[derive(Debug)]
+pub enum EditorError {
+    /// Neither VISUAL nor EDITOR is set.
+    NoEditor,
+    /// The editor variable leaves a quote open.
+    Quotes { variable: &'static str, value: String },
+    /// The result was read from standard input, there is no file to open.
+    Stdin,
+    /// A template has a { without its }.
+    Unclosed { word: String },
+    /// A placeholder is not a valid source field.
+    Spec(SpecError),
+    /// A placeholder names no field of `Location`.
+    Entry(framework::Error),
+    /// The quickfix file could not be written.
+    Quickfix(io::Error),
+}
+impl Display for EditorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditorError::NoEditor => write!(f, "no editor, set VISUAL or EDITOR"),
+            EditorError::Quotes { variable, value } => write!(f, "{}: unclosed quote in `{}`", variable, value),
+            EditorError::Stdin => write!(f, "the result was read from standard input, there is no file to open"),
+            EditorError::Unclosed { word } => write!(f, "editor template `{}`: unclosed {{", word),
+            EditorError::Spec(e) => write!(f, "editor template: {}", e),
+            EditorError::Entry(e) => write!(f, "editor template: {}", e),
+            EditorError::Quickfix(e) => write!(f, "could not write the quickfix file: {}", e),
+        }
+    }
+}
+impl std::error::Error for EditorError {}
+impl From<SpecError> for EditorError {
+    fn from(e: SpecError) -> Self {
+        return EditorError::Spec(e);
+    }
+}
+impl From<framework::Error> for EditorError {
+    fn from(e: framework::Error) -> Self {
+        return EditorError::Entry(e);
+    }
+}
+
+/// Where an editor should go. Lines and columns start from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    /// In characters.
+    pub column: usize,
+    /// In bytes.
+    pub byte_column: usize,
+    /// The result, with its path made absolute.
+    pub result: Match,
+}
+
+impl Location {
+    /// Where a result is. Results without a column go to the start of their line.
+    pub fn of(set: &ResultSet, m: &Match) -> Result<Self, EditorError> {
+        if m.path == Path::new(STDIN_PATH) {
+            return Err(EditorError::Stdin);
+        }
+        let byte_column = m.column.unwrap_or(1);
+        let column = m.text.get(..byte_column.saturating_sub(1)).map(|before| before.chars().count() + 1).unwrap_or(byte_column);
+        let result = Match { path: set.locate(m), ..m.clone() };
+        return Ok(Location { file: result.path.clone(), line: m.line, column, byte_column, result });
+    }
+
+    /// Fills an entry with the field it reads from, as `Args::fill` does for backends.
+    pub fn fill(&self, entry: &mut Entry) -> Result<(), framework::Error> {
+        match entry.source_field.as_deref() {
+            Some("file") => entry.fill(&self.file),
+            Some("line") => entry.fill(&self.line.to_string()),
+            Some("column") => entry.fill(&self.column.to_string()),
+            Some("byte_column") => entry.fill(&self.byte_column.to_string()),
+            field => Err(framework::Error { entry: entry.label(), kind: ErrorKind::UnknownSource(field.unwrap_or_default().to_string()) }),
+        }
+    }
+}
+
+/// The user's editor, as a program and its own arguments, i.e. emacsclient -t. Both are split into words as sh would,
+/// so that paths with spaces can be quoted.
+pub fn editor() -> Result<Vec<String>, EditorError> {
+    let (variable, value) = ["VISUAL", "EDITOR"].into_iter()
+        .filter_map(|v| env::var_os(v).map(|e| (v, e.to_string_lossy().into_owned())))
+        .find(|(_, e)| !e.trim().is_empty())
+        .ok_or(EditorError::NoEditor)?;
+    return shell::split(&value).ok_or(EditorError::Quotes { variable, value });
+}
+
+/// The template of a program, and how it opens several results: the user's template, which is run once per result,
+/// then the built-in one, then `UNKNOWN`.
+pub fn template(program: &str, overrides: &BTreeMap<String, Vec<String>>) -> (Vec<String>, Many) {
+    let name = Path::new(program).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+    if let Some(t) = overrides.get(name) {
+        return (t.clone(), Many::Sequential);
+    }
+    let known = EDITORS.iter().find(|k| k.names.contains(&name)).unwrap_or(&UNKNOWN);
+    return (known.template.iter().map(|w| w.to_string()).collect(), known.many);
+}
+
+/// Writes a template word for a location. Each placeholder is a mandatory text entry, filled from the location.
+pub fn expand(word: &str, at: &Location) -> Result<String, EditorError> {
+    let mut r = String::new();
+    let mut rest = word;
+    while let Some(open) = rest.find('{') {
+        r.push_str(&rest[..open]);
+        let close = rest[open..].find('}').ok_or_else(|| EditorError::Unclosed { word: word.to_string() })? + open;
+        let mut entry = Entry::try_from(format!("#0!{}[str]", &rest[open..=close]).as_str())?;
+        at.fill(&mut entry)?;
+        r.push_str(&entry.generate()?.concat());
+        rest = &rest[close + 1..];
+    }
+    r.push_str(rest);
+    return Ok(r);
+}
+
+/// What opens several locations.
+#[derive(Debug)]
+pub struct Launch {
+    /// The command lines to run, one after the other.
+    pub lines: Vec<Vec<String>>,
+    /// The quickfix file they read, removed once dropped.
+    pub quickfix: Option<NamedTempFile>,
+}
+
+/// How to open `editor` at every location, in one go when it can. A single location never needs a quickfix file.
+pub fn launch(editor: &[String], overrides: &BTreeMap<String, Vec<String>>, at: &[Location]) -> Result<Launch, EditorError> {
+    let (template, many) = template(editor.first().map_or("", String::as_str), overrides);
+    let words = |at: &Location| template.iter().map(|w| expand(w, at)).collect::<Result<Vec<_>, _>>();
+    let single = at.len() <= 1;
+    match many {
+        Many::Quickfix(flag) if !single => {
+            let file = quickfix(at).map_err(EditorError::Quickfix)?;
+            let mut line = editor.to_vec();
+            line.extend([flag.to_string(), file.path().display().to_string()]);
+            return Ok(Launch { lines: vec![line], quickfix: Some(file) });
+        },
+        Many::Sequential if !single => {
+            let lines = at.iter().map(|a| words(a).map(|w| [editor, &w].concat())).collect::<Result<_, _>>()?;
+            return Ok(Launch { lines, quickfix: None });
+        },
+        _ => {
+            let mut line = editor.to_vec();
+            for a in at {
+                line.extend(words(a)?);
+            }
+            return Ok(Launch { lines: vec![line], quickfix: None });
+        },
+    }
+}
+
+/// Writes the locations to a temporary file, for vim.
+fn quickfix(at: &[Location]) -> io::Result<NamedTempFile> {
+    let mut file = NamedTempFile::new()?;
+    for a in at {
+        for l in export::Format::Vim.lines(Path::new(""), &a.result) {
+            writeln!(file, "{}", l)?;
+        }
+    }
+    file.flush()?;
+    return Ok(file);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let mut result = Match::new(PathBuf::from("/src/é.rs"), 12, "xé z".to_string());
+        result.spans.push(3..4);
+        let at = Location { file: result.path.clone(), line: 12, column: 3, byte_column: 4, result };
+        let command = |editor: &[String], overrides: &BTreeMap<String, Vec<String>>, at: &Location| launch(editor, overrides, std::slice::from_ref(at)).map(|mut l| l.lines.remove(0));
+        let line = |editor: &[&str]| command(&editor.iter().map(|w| w.to_string()).collect::<Vec<_>>(), &BTreeMap::new(), &at).unwrap();
+        assert_eq!(line(&["/usr/bin/nvim"]), ["/usr/bin/nvim", "+call cursor(12,4)", "/src/é.rs"]);
+        assert_eq!(line(&["emacsclient", "-t"]), ["emacsclient", "-t", "+12:3", "/src/é.rs"]);
+        assert_eq!(line(&["code"]), ["code", "-g", "/src/é.rs:12:3"]);
+        assert_eq!(line(&["nano"]), ["nano", "+12,3", "/src/é.rs"]);
+        assert_eq!(line(&["ed"]), ["ed", "/src/é.rs"]);
+
+        let overrides = BTreeMap::from([("ed".to_string(), vec!["{line}".to_string()])]);
+        assert_eq!(command(&["ed".to_string()], &overrides, &at).unwrap(), ["ed", "12"]);
+        assert_eq!(expand("{colum}", &at).unwrap_err().to_string(), "editor template: entry `colum`: there is no source field `colum`");
+        assert!(matches!(expand("+{line", &at), Err(EditorError::Unclosed { .. })));
+
+        let two = [at.clone(), Location { line: 20, result: Match { line: 20, ..at.result.clone() }, ..at.clone() }];
+        let open = |editor: &str| launch(&[editor.to_string()], &overrides, &two).unwrap();
+        assert_eq!(open("hx").lines, [["hx", "/src/é.rs:12:3", "/src/é.rs:20:3"]]);
+        assert_eq!(open("ed").lines, [["ed", "12"], ["ed", "20"]]);
+        let vim = open("vim");
+        let file = vim.quickfix.unwrap();
+        assert_eq!(vim.lines, [["vim".to_string(), "-q".to_string(), file.path().display().to_string()]]);
+        assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "/src/é.rs:12:4:xé z\n/src/é.rs:20:4:xé z\n");
+    }
+}
@@ -1,3 +1,136 @@
+#![allow(clippy::needless_return)]
+mod editor;
+mod selection;
+mod show;
+
+use std::io::{self, IsTerminal, Write};
+use clap::Parser;
+
+use cg::cache;
+use cg::command::config;
+use cg::command::shell::{self, Shell};
+use cg::export;
+use cg::matches::{Color, STDIN_PATH};
+
+#[derive(Parser, Debug)]
+#[command(author = "SliceOfArdath", version, about = "Go to what cg found.", long_about = None)]
+struct Args {
+    /// The results to open, as numbered by cg: 3-7,12 or all or file:4, every result in the file of result 4.
+    /// Without any, the results are listed, or all exported.
+    #[arg(value_name="SELECTION")]
+    selection: Option<selection::Selection>,
+    /// Print the editor command line instead of running it, quoted for sh, bash, fish or powershell.
+    #[arg(long, value_name="SHELL", num_args=0..=1, require_equals=true, default_missing_value="sh")]
+    print_command: Option<Shell>,
+    /// Show the results with the lines around them, instead of opening them.
+    #[arg(long, requires="selection")]
+    show: bool,
+    /// How many lines to show before and after each result.
+    #[arg(short='C', long, value_name="LINES", default_value_t=2, requires="show")]
+    context: usize,
+    /// Print the results for an editor instead of opening them: vim, emacs or vscode.
+    #[arg(long, value_name="FORMAT")]
+    export: Option<export::Format>,
+    /// When to colour the list: auto, always or never. Auto colours terminals, unless NO_COLOR is set.
+    #[arg(long, value_name="WHEN", default_value_t)]
+    color: Color,
+}
+
+/// Opens results of the last search, or lists them all. Returns the exit code.
+fn run() -> Result<i32, Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let set = cache::read(&cache::path()?)?;
+    if let Some(format) = args.export {
+        let chosen = match &args.selection {
+            Some(s) => s.resolve(&set)?.into_iter().map(|i| &set.results[i]).collect(),
+            None => set.results.iter().collect::<Vec<_>>(),
+        };
+        match export::write(&set, &chosen, format, &mut io::stdout().lock()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => return Ok(0),
+        }
+    }
+    let Some(selection) = args.selection else {
+        let colors = args.color.enabled(io::stdout().is_terminal());
+        let mut out = io::stdout().lock();
+        for (i, m) in set.results.iter().enumerate() {
+            match writeln!(out, "{}", m.list(i + 1, true, colors)) {
+                //Whoever reads the list has seen enough, i.e. vg | head.
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+                r => r?,
+            }
+        }
+        return Ok(0);
+    };
+    if args.show {
+        let colors = args.color.enabled(io::stdout().is_terminal());
+        let mut out = io::stdout().lock();
+        let chosen = selection.resolve(&set)?;
+        let mut shown = 0;
+        for i in &chosen {
+            let m = &set.results[*i];
+            if m.path.as_os_str() == STDIN_PATH {
+                eprintln!("vg: result {}: the result was read from standard input, there is no file to show, skipped", i + 1);
+                continue;
+            }
+            if shown > 0 {
+                writeln!(out, "--")?;
+            }
+            shown += 1;
+            let path = set.locate(m);
+            let same = show::show(&path, m, args.context, colors, &mut out).map_err(|e| format!("result {}: {}: {}", i + 1, path.display(), e))?;
+            if !same {
+                eprintln!("vg: result {}: line {} of {} changed since the search", i + 1, m.line, path.display());
+            }
+        }
+        if shown == 0 && !chosen.is_empty() {
+            return Err("nothing to show, every result selected was read from standard input".into());
+        }
+        return Ok(0);
+    }
+    let mut at = Vec::new();
+    let chosen = selection.resolve(&set)?;
+    for i in &chosen {
+        //Results read from standard input have no file, the others still open.
+        match editor::Location::of(&set, &set.results[*i]) {
+            Ok(a) => at.push(a),
+            Err(e @ editor::EditorError::Stdin) => eprintln!("vg: result {}: {}, skipped", i + 1, e),
+            Err(e) => return Err(format!("result {}: {}", i + 1, e).into()),
+        }
+    }
+    if chosen.is_empty() {
+        return Err("nothing selected, the last search found nothing".into());
+    }
+    if at.is_empty() {
+        return Err("nothing to open, every result selected was read from standard input".into());
+    }
+    let launch = editor::launch(&editor::editor()?, &config::settings()?.editors, &at)?;
+    if let Some(shell) = args.print_command {
+        //The printed command lines still need the quickfix file.
+        if let Some(file) = launch.quickfix {
+            file.keep()?;
+        }
+        for l in &launch.lines {
+            println!("{}", shell::join(l, shell));
+        }
+        return Ok(0);
+    }
+    for line in &launch.lines {
+        //The editor stays in vg's process group, so that it keeps the terminal.
+        let status = std::process::Command::new(&line[0]).args(&line[1..]).status().map_err(|e| format!("{}: {}", line[0], e))?;
+        if !status.success() {
+            return Ok(status.code().unwrap_or(2));
+        }
+    }
+    return Ok(0);
+}
+
 fn main() {
-    println!("Hello, world!");
+    match run() {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("vg: {}", e);
+            std::process::exit(2);
+        },
+    }
 }
@@ -0,0 +1,93 @@
+//! Which results to open, as written on the command line: 3-7,12 or all or file:4.
+use std::str::FromStr;
+
+use cg::cache::ResultSet;
+
+/// A part of a selection, between commas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Item {
+    /// Every result.
+    All,
+    /// A single result.
+    One(usize),
+    /// Results from the first to the last, both included.
+    Range(usize, usize),
+    /// Every result in the file of this one.
+    File(usize),
+}
+
+/// Results, by the numbers cg listed them under.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection(pub Vec<Item>);
+
+impl FromStr for Selection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |n: &str| n.trim().parse::<usize>().map_err(|_| format!("invalid selection `{}`: `{}` is not a result number", s, n.trim()));
+        let mut items = Vec::new();
+        for part in s.split(',').map(str::trim) {
+            let item = if part == "all" {
+                Item::All
+            } else if let Some(n) = part.strip_prefix("file:") {
+                Item::File(number(n)?)
+            } else if let Some((first, last)) = part.split_once('-') {
+                let (first, last) = (number(first)?, number(last)?);
+                if first > last {
+                    return Err(format!("invalid selection `{}`: {} comes after {}", s, first, last));
+                }
+                Item::Range(first, last)
+            } else {
+                Item::One(number(part)?)
+            };
+            items.push(item);
+        }
+        return Ok(Selection(items));
+    }
+}
+
+impl Selection {
+    /// The indices of the selected results in `set`, each once, in the order they were asked for.
+    pub fn resolve(&self, set: &ResultSet) -> Result<Vec<usize>, String> {
+        let count = set.results.len();
+        let check = |n: usize| if (1..=count).contains(&n) { Ok(n - 1) } else { Err(format!("no result {}, the last search found {}", n, count)) };
+        let mut r = Vec::new();
+        for item in &self.0 {
+            match *item {
+                Item::All => r.extend(0..count),
+                Item::One(n) => r.push(check(n)?),
+                Item::Range(first, last) => {
+                    check(last)?;
+                    r.extend(check(first)?..last);
+                },
+                Item::File(n) => {
+                    let path = &set.results[check(n)?].path;
+                    r.extend((0..count).filter(|i| &set.results[*i].path == path));
+                },
+            }
+        }
+        let mut seen = vec![false; count];
+        r.retain(|i| !std::mem::replace(&mut seen[*i], true));
+        return Ok(r);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use cg::matches::Match;
+
+    #[test]
+    fn selections() {
+        let results = ["a", "a", "b", "a", "c"].iter().map(|p| Match::new(PathBuf::from(p), 1, String::new())).collect();
+        let set = ResultSet { version: cg::cache::VERSION, directory: PathBuf::from("/"), patterns: vec![], results };
+        let resolve = |s: &str| s.parse::<Selection>().and_then(|s| s.resolve(&set));
+        assert_eq!(resolve("3-4, 1,3"), Ok(vec![2, 3, 0]));
+        assert_eq!(resolve("file:2,5"), Ok(vec![0, 1, 3, 4]));
+        assert_eq!(resolve("all"), Ok(vec![0, 1, 2, 3, 4]));
+        assert_eq!(resolve("2-6"), Err("no result 6, the last search found 5".to_string()));
+        assert_eq!(resolve("0"), Err("no result 0, the last search found 5".to_string()));
+        assert_eq!(resolve("4-2"), Err("invalid selection `4-2`: 4 comes after 2".to_string()));
+        assert_eq!(resolve("file:x"), Err("invalid selection `file:x`: `x` is not a result number".to_string()));
+    }
+}
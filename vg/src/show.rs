@@ -0,0 +1,55 @@
+//! Showing results where they are, with the lines around them, read from the file as it is now.
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use cg::matches::{Match, LINE, PATH, RESET};
+
+/// Writes the path of a result, then its line and `context` lines on each side, numbered as grep does: n: for the
+/// result, n- around it. The matches are highlighted when `colors` is set.
+/// Returns whether the file still holds the line as it was found. When it does not, nothing is highlighted.
+pub fn show(path: &Path, m: &Match, context: usize, colors: bool, out: &mut impl Write) -> io::Result<bool> {
+    let content = fs::read(path)?;
+    let content = String::from_utf8_lossy(&content);
+    let lines: Vec<&str> = content.lines().collect();
+    let same = m.line.checked_sub(1).and_then(|i| lines.get(i)) == Some(&m.text.as_str());
+
+    let first = m.line.saturating_sub(context).max(1);
+    let last = (m.line + context).min(lines.len());
+    let width = last.max(m.line).to_string().len();
+    let paint = |text: &str, color: &str| if colors { format!("{}{}{}", color, text, RESET) } else { text.to_string() };
+    writeln!(out, "{}", paint(&path.display().to_string(), PATH))?;
+    for n in first..=last {
+        let separator = if n == m.line { ':' } else { '-' };
+        let text = if n == m.line && same && colors { m.colored_text() } else { lines[n - 1].to_string() };
+        writeln!(out, "{}{}{}", paint(&format!("{:>width$}", n), LINE), separator, text)?;
+    }
+    return Ok(same);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn context() {
+        let path = std::env::temp_dir().join(format!("vg-show-{}", std::process::id()));
+        fs::write(&path, (1..=12).map(|n| format!("line {}\n", n)).collect::<String>()).unwrap();
+        let mut m = Match::new(PathBuf::from("f"), 10, "line 10".to_string());
+        m.spans.push(5..7);
+        let shown = |m: &Match, colors| {
+            let mut out = Vec::new();
+            let same = show(&path, m, 2, colors, &mut out).unwrap();
+            return (same, String::from_utf8(out).unwrap());
+        };
+        let header = path.display().to_string();
+        assert_eq!(shown(&m, false), (true, format!("{}\n 8-line 8\n 9-line 9\n10:line 10\n11-line 11\n12-line 12\n", header)));
+        assert!(shown(&m, true).1.contains(":line \x1b[1;31m10\x1b[0m\n"));
+
+        m.text = "line ten".to_string();
+        let (same, out) = shown(&m, true);
+        fs::remove_file(&path).unwrap();
+        assert!(!same && out.contains(":line 10\n"));
+    }
+}